@@ -1,4 +1,5 @@
 use std::{env};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::path::PathBuf;
 use anyhow::{anyhow, Context};
@@ -41,6 +42,9 @@ pub struct Config {
 
     #[serde(default)]
     pub security: ConfigSecurity,
+
+    #[serde(default)]
+    pub hooks: ConfigHooks,
 }
 
 impl Config {
@@ -65,7 +69,8 @@ impl Default for Config {
             cache_dir: cache::default_cache_dir(),
             system_variables: variables::default_system_variables(),
             system: Default::default(),
-            security: Default::default()
+            security: Default::default(),
+            hooks: Default::default()
         }
     }
 }
@@ -83,6 +88,13 @@ pub struct ConfigShell {
     pub clean_terminal: bool,
     #[serde(default)]
     pub package_manager: ConfigPackage,
+    /// additional package managers, which package jobs can select by their name
+    #[serde(default)]
+    pub package_managers: BTreeMap<String, ConfigPackage>,
+    #[serde(default)]
+    pub service_manager: ConfigServices,
+    #[serde(default)]
+    pub timers: ConfigTimers,
     pub default_directory: Option<String>
 }
 
@@ -109,6 +121,9 @@ impl Default for ConfigShell {
             root_elevator: ConfigShell::root_elevator_default(),
             file_previewer: ConfigShell::file_previewer_default(),
             package_manager: Default::default(),
+            package_managers: Default::default(),
+            service_manager: Default::default(),
+            timers: Default::default(),
             clean_terminal: ConfigShell::clean_terminal_default(),
             default_directory: None
         }
@@ -116,11 +131,17 @@ impl Default for ConfigShell {
 }
 
 /// This struct contains configuration about the package manager, having dummy defaults
-#[derive(Deserialize, Clone, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 pub struct ConfigPackage {
+    /// identity of the package manager, used to select package names, e.g. pacman or apt
+    #[serde(default)]
+    pub name: Option<String>,
     pub root: bool,
     pub install: String,
-    pub remove: String
+    pub remove: String,
+    /// command which prints the given packages which are installed, one per line
+    #[serde(default)]
+    pub query: Option<String>
 }
 
 impl Default for ConfigPackage {
@@ -128,7 +149,9 @@ impl Default for ConfigPackage {
         Self {
             install: "echo \"Package manager is not configured yet\"; exit 1".to_owned(),
             remove: "echo \"Package manager is not configured yet\"; exit 1".to_owned(),
-            root: false
+            root: false,
+            name: None,
+            query: None
         }
     }
 }
@@ -143,6 +166,156 @@ impl ConfigPackage {
         let packages = packages.join(" ");
         self.remove.clone().replace("%PACKAGE%", &packages)
     }
+
+    pub fn create_query(&self, packages: &[String]) -> Option<String> {
+        let packages = packages.join(" ");
+        self.query.as_ref().map(|query| query.replace("%PACKAGE%", &packages))
+    }
+}
+
+/// This struct contains the commands of the service manager for system and user services, using systemd by default
+#[derive(Deserialize, Clone, JsonSchema)]
+pub struct ConfigServices {
+    #[serde(default="ConfigServices::system_default")]
+    pub system: ConfigService,
+    #[serde(default="ConfigServices::user_default")]
+    pub user: ConfigService
+}
+
+impl ConfigServices {
+    /// The default commands for system services
+    pub fn system_default() -> ConfigService {
+        ConfigService {
+            root: true,
+            enable: "systemctl enable --now %SERVICE%".to_owned(),
+            disable: "systemctl disable --now %SERVICE%".to_owned(),
+            restart: "systemctl restart %SERVICE%".to_owned()
+        }
+    }
+
+    /// The default commands for user services
+    pub fn user_default() -> ConfigService {
+        ConfigService {
+            root: false,
+            enable: "systemctl --user enable --now %SERVICE%".to_owned(),
+            disable: "systemctl --user disable --now %SERVICE%".to_owned(),
+            restart: "systemctl --user restart %SERVICE%".to_owned()
+        }
+    }
+}
+
+impl Default for ConfigServices {
+    fn default() -> Self {
+        Self {
+            system: ConfigServices::system_default(),
+            user: ConfigServices::user_default()
+        }
+    }
+}
+
+/// This struct contains the commands to manage services of one scope
+#[derive(Deserialize, Clone, JsonSchema)]
+pub struct ConfigService {
+    pub root: bool,
+    /// command which enables and starts a service
+    pub enable: String,
+    /// command which disables and stops a service
+    pub disable: String,
+    /// command which restarts a service
+    pub restart: String
+}
+
+impl ConfigService {
+    pub fn create_enable(&self, service: &str) -> String {
+        self.enable.replace("%SERVICE%", service)
+    }
+
+    pub fn create_disable(&self, service: &str) -> String {
+        self.disable.replace("%SERVICE%", service)
+    }
+
+    pub fn create_restart(&self, service: &str) -> String {
+        self.restart.replace("%SERVICE%", service)
+    }
+}
+
+/// This struct contains configuration about how scheduled tasks are installed, using systemd timers by default
+#[derive(Deserialize, Clone, JsonSchema)]
+pub struct ConfigTimers {
+    #[serde(default)]
+    pub backend: TimerBackend,
+    #[serde(default="ConfigTimers::system_default")]
+    pub system: ConfigTimerScope,
+    #[serde(default="ConfigTimers::user_default")]
+    pub user: ConfigTimerScope
+}
+
+impl ConfigTimers {
+    /// The default configuration for system tasks
+    pub fn system_default() -> ConfigTimerScope {
+        ConfigTimerScope {
+            root: true,
+            units: "/etc/systemd/system".to_owned(),
+            reload: "systemctl daemon-reload".to_owned(),
+            crontab_read: "crontab -l".to_owned(),
+            crontab_write: "crontab -".to_owned()
+        }
+    }
+
+    /// The default configuration for user tasks
+    pub fn user_default() -> ConfigTimerScope {
+        ConfigTimerScope {
+            root: false,
+            units: "~/.config/systemd/user".to_owned(),
+            reload: "systemctl --user daemon-reload".to_owned(),
+            crontab_read: "crontab -l".to_owned(),
+            crontab_write: "crontab -".to_owned()
+        }
+    }
+}
+
+impl Default for ConfigTimers {
+    fn default() -> Self {
+        Self {
+            backend: Default::default(),
+            system: ConfigTimers::system_default(),
+            user: ConfigTimers::user_default()
+        }
+    }
+}
+
+/// This enum represents the program which runs scheduled tasks
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+pub enum TimerBackend {
+    #[default]
+    #[serde(rename="systemd", alias="Systemd")]
+    Systemd,
+    #[serde(rename="cron", alias="Cron")]
+    Cron
+}
+
+/// This struct contains configuration about scheduled tasks of one scope
+#[derive(Deserialize, Clone, JsonSchema)]
+pub struct ConfigTimerScope {
+    pub root: bool,
+    /// directory the unit files are written to, for systemd
+    pub units: String,
+    /// command which reloads the unit files, for systemd
+    pub reload: String,
+    /// command which prints the crontab, for cron
+    pub crontab_read: String,
+    /// command which replaces the crontab with its input, for cron
+    pub crontab_write: String
+}
+
+/// This struct contains commands which are run around every modification of the system
+#[derive(Deserialize, Clone, Default, JsonSchema)]
+#[schemars(title = "Hooks")]
+pub struct ConfigHooks {
+    /// command run before modules are modified, receiving the modules as json on stdin
+    pub before_modify: Option<String>,
+    /// command run after modules were modified, receiving the modules as json on stdin
+    pub after_modify: Option<String>
 }
 
 /// This enum represents a strategy used to confirm changes to the system
@@ -1,34 +1,33 @@
+use std::collections::BTreeSet;
 use std::fs;
 use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
 use crate::jobs::{BuiltJob, JobEnvironment, JobError, JobResult, ResourceItem};
-use crate::variables::context::read_context;
-use crate::variables::evaluate::{evaluate, VariableEvalCounter};
+use crate::module::MODULE_CONFIG;
+use crate::variables::context::{read_context, read_expression};
+use crate::variables::evaluate::{evaluate, evaluate_condition, VariableEvalCounter};
 
 // loads a resource from file to a string and throws an error if not found
 pub fn resource_load(file: &Path, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<String> {
-    let mut path = env.path.clone();
-    path.push(file);
+    let path = env.resolve(file);
 
     let content = fs::read_to_string(&path).map_err(|e| JobError::Resources(file.to_owned(), e))?;
     // calculate checksum after reading, so the errors are more informative
-    built.mark_resource(ResourceItem::create(file.to_owned(), &env.path)?);
+    built.mark_resource(ResourceItem::create(file.to_owned(), env)?);
 
     Ok(content)
 }
 
 // checks a resource whether it is a file or not
 pub fn resource_dir(file: &Path, env: &JobEnvironment) -> JobResult<bool> {
-    let mut path = env.path.clone();
-    path.push(file);
+    let path = env.resolve(file);
 
     Ok(path.is_dir())
 }
 
 // checks that a resource exists and throws an error otherwise
 pub fn resource_mark(file: &Path, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<PathBuf> {
-    let mut path = env.path.clone();
-    path.push(file);
+    let path = env.resolve(file);
 
     // check existence
     if !path.exists() {
@@ -36,21 +35,60 @@ pub fn resource_mark(file: &Path, env: &JobEnvironment, built: &mut BuiltJob) ->
     }
 
     // TODO: Do this at the correct point in time, after the prompt
-    built.mark_resource(ResourceItem::create(file.to_owned(), &env.path)?);
+    built.mark_resource(ResourceItem::create(file.to_owned(), env)?);
 
     Ok(path)
 }
 
+// lists all files inside a resource directory recursively, relative to the module, merged across all resource directories
+pub fn resource_files(directory: &Path, env: &JobEnvironment) -> JobResult<BTreeSet<PathBuf>> {
+    fn walk(dir: &Path, relative: &Path, found: &mut BTreeSet<PathBuf>) -> std::io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+
+            if entry.path().is_dir() { walk(&entry.path(), &path, found)?; }
+            else { found.insert(path); }
+        }
+
+        Ok(())
+    }
+
+    let mut found = BTreeSet::new();
+    for dir in env.resources.iter().map(|d| d.join(directory)).filter(|d| d.is_dir()) {
+        walk(&dir, directory, &mut found).map_err(|e| JobError::Resources(directory.to_owned(), e))?;
+    }
+
+    if found.is_empty() && !env.resolve(directory).is_dir() {
+        return Err(JobError::Resources(directory.to_owned(), Error::other("directory does not exist")))
+    }
+
+    Ok(found)
+}
+
 // processes the variables inside a given string, and throws an error if it could not be resolved
 pub fn process_variables(string: &str, path: &Path, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<String> {
 
     // parses the file and creates a context
-    let context = read_context(string).map_err(|e| JobError::Variable(e, string.to_owned(), path.to_owned()))?;
+    let context = read_context(string).map_err(|e| JobError::Variable(Box::new(e), string.to_owned(), path.to_owned()))?;
 
     // evaluates the context
     let mut counter = VariableEvalCounter::default();
-    let result = evaluate(string, &context, env.variables, &mut counter).map_err(|e| JobError::Variable(e, string.to_owned(), path.to_owned()))?;
+    let result = evaluate(string, &context, env.variables, &mut counter).map_err(|e| JobError::Variable(Box::new(e), string.to_owned(), path.to_owned()))?;
     built.use_variables(counter);
 
     Ok(result)
-}
\ No newline at end of file
+}
+
+// evaluates a condition expression of the module file, and throws an error if it could not be resolved
+pub fn process_condition(expression: &str, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<bool> {
+    let file = env.path.join(MODULE_CONFIG);
+
+    let expr = read_expression(expression).map_err(|e| JobError::Variable(Box::new(e), expression.to_owned(), file.clone()))?;
+
+    let mut counter = VariableEvalCounter::default();
+    let result = evaluate_condition(&expr, env.variables, &mut counter).map_err(|e| JobError::Variable(Box::new(e), expression.to_owned(), file))?;
+    built.use_variables(counter);
+
+    Ok(result)
+}
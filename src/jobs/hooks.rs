@@ -0,0 +1,102 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::jobs::{BuiltJob, JobEnvironment, JobError, JobResult};
+use crate::jobs::helper::{process_variables, resource_load};
+use crate::module::change::{RunChange, ScriptChange};
+
+/// Hooks of a module, which are run once per module operation around its jobs
+#[derive(Serialize, Deserialize, Clone, Default, Eq, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct ModuleHooks {
+    /// runs before the jobs are installed
+    pre_install: Option<Hook>,
+    /// runs after all jobs have been installed
+    post_install: Option<Hook>,
+    /// runs after the module has been updated
+    post_update: Option<Hook>,
+    /// runs before the jobs are removed
+    pre_remove: Option<Hook>
+}
+
+/// A single hook, which is either a command or a script
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Hook {
+    /// command to run on the shell
+    command: Option<String>,
+    /// script file inside the module directory to run
+    script: Option<String>,
+
+    show_output: Option<bool>,
+    root: Option<bool>
+}
+
+/// Built hooks of a module, stored so they can be run when the module is removed
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct BuiltHooks {
+    pub pre_install: Option<BuiltJob>,
+    pub post_install: Option<BuiltJob>,
+    pub post_update: Option<BuiltJob>,
+    pub pre_remove: Option<BuiltJob>
+}
+
+impl ModuleHooks {
+
+    /// Takes the hooks of a base module for all hooks which are not set
+    pub fn inherit(&mut self, base: &ModuleHooks) {
+        self.pre_install = self.pre_install.take().or_else(|| base.pre_install.clone());
+        self.post_install = self.post_install.take().or_else(|| base.post_install.clone());
+        self.post_update = self.post_update.take().or_else(|| base.post_update.clone());
+        self.pre_remove = self.pre_remove.take().or_else(|| base.pre_remove.clone());
+    }
+
+    pub fn build(&self, env: &JobEnvironment) -> JobResult<BuiltHooks> {
+        let build = |hook: &Option<Hook>, name: &str| hook.as_ref().map(|h| h.build(name, env)).transpose();
+
+        Ok(BuiltHooks {
+            pre_install: build(&self.pre_install, "pre-install")?,
+            post_install: build(&self.post_install, "post-install")?,
+            post_update: build(&self.post_update, "post-update")?,
+            pre_remove: build(&self.pre_remove, "pre-remove")?
+        })
+    }
+}
+
+impl Hook {
+    fn build(&self, name: &str, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+        built.title = format!("Running the {name} hook");
+
+        let interactive = self.show_output.unwrap_or(true);
+
+        match (&self.command, &self.script) {
+            (Some(command), None) => {
+                let command = process_variables(command, Path::new(&format!("{name}-hook")), env, &mut built)?;
+                built.change(Box::new(RunChange::new(command, None, env.path.clone(), interactive, false)));
+            }
+            (None, Some(script)) => {
+                let path = Path::new(script);
+
+                let script = resource_load(path, env, &mut built)?;
+                let script = process_variables(&script, path, env, &mut built)?;
+                built.change(Box::new(ScriptChange::new(script, None, env.path.clone(), interactive, false)));
+            }
+            _ => {
+                return Err(JobError::Other(format!("the {name} hook is invalid"), Error::new(ErrorKind::InvalidInput, "exactly one of command or script must be set").into()))
+            }
+        }
+
+        built.root = self.root.unwrap_or_default();
+
+        Ok(built)
+    }
+}
+
+impl BuiltHooks {
+    /// Returns all built hooks
+    pub fn jobs(&self) -> impl Iterator<Item = &BuiltJob> {
+        [&self.pre_install, &self.post_install, &self.post_update, &self.pre_remove].into_iter().flatten()
+    }
+}
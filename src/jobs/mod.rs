@@ -1,16 +1,18 @@
 mod types;
 mod helper;
+pub mod hooks;
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::fs::File;
 use std::io::Error;
 use std::path::{Path, PathBuf};
-use anyhow::Context;
+use anyhow::anyhow;
 use chksum::chksum;
 use chksum::hash::SHA1;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::config::ConfigPackage;
+use crate::config::{ConfigPackage, ConfigServices, ConfigTimers};
 use crate::jobs::types::Installable;
 use crate::module::change::AtomicChange;
 use crate::variables::{Variable, VariableError};
@@ -20,7 +22,75 @@ use crate::variables::evaluate::VariableEvalCounter;
 pub struct JobEnvironment<'a> {
     pub variables: &'a Variable,
     pub path: PathBuf,
-    pub package_config: ConfigPackage
+    /// directories resources are searched in, ordered by precedence
+    pub resources: Vec<PathBuf>,
+    pub package_config: ConfigPackage,
+    /// additional package managers, by their name
+    pub package_managers: BTreeMap<String, ConfigPackage>,
+    pub service_config: ConfigServices,
+    pub timer_config: ConfigTimers
+}
+
+impl JobEnvironment<'_> {
+    /// resolves a resource to the first resource directory containing it, falling back to the module directory
+    pub fn resolve(&self, file: &Path) -> PathBuf {
+        self.resources.iter()
+            .map(|dir| dir.join(file))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| self.path.join(file))
+    }
+
+    /// returns the package manager with the given name, or the default package manager if there is no name
+    pub fn package_manager(&self, name: Option<&str>) -> Option<&ConfigPackage> {
+        match name {
+            Some(name) => self.package_managers.get(name),
+            None => Some(&self.package_config)
+        }
+    }
+}
+
+/// Temporary directory jobs are built and applied in during tests, which is removed again when dropped
+#[cfg(test)]
+pub struct TestDir {
+    pub path: PathBuf,
+    variables: Variable
+}
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("pusta-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("temporary directory should be creatable");
+
+        Self { path, variables: Variable::base() }
+    }
+
+    /// Creates an environment with the default config, using the directory as module and resource directory
+    pub fn env(&self) -> JobEnvironment<'_> {
+        JobEnvironment {
+            variables: &self.variables,
+            path: self.path.clone(),
+            resources: vec![self.path.clone()],
+            package_config: Default::default(),
+            package_managers: Default::default(),
+            service_config: Default::default(),
+            timer_config: Default::default()
+        }
+    }
+
+    /// Creates a runtime caching inside the directory, like the cache of a module
+    pub fn runtime(&self) -> crate::module::change::ChangeRuntime {
+        fs::create_dir_all(self.path.join("cache")).expect("cache directory should be creatable");
+        crate::module::change::ChangeRuntime { cache: self.path.join("cache"), temp: self.path.join("temp") }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
 }
 
 /// this marks a resource used by the job
@@ -34,29 +104,32 @@ pub struct ResourceItem {
 
 impl ResourceItem {
     /// creates the item and calculates the checksum
-    pub fn create(path: PathBuf, parent: &Path) -> JobResult<Self> {
-        let mut file = parent.to_owned();
-        file.push(&path);
+    pub fn create(path: PathBuf, env: &JobEnvironment) -> JobResult<Self> {
+        let checksum = Self::checksum(&env.resolve(&path))?;
 
-        let checksum = if file.is_dir() {
+        Ok(Self { path, checksum })
+    }
+
+    /// calculates the checksum of a file or directory
+    fn checksum(file: &Path) -> JobResult<String> {
+        if file.is_dir() {
             let handle = fs::read_dir(file).map_err(|e| JobError::Other("could not open directory to calculate checksum".into(), e.into()))?;
-            chksum::<SHA1, _>(handle).map_err(|e| JobError::Other("could not calculate checksum of directory".into(), e.into()))?.to_hex_lowercase()
+            Ok(chksum::<SHA1, _>(handle).map_err(|e| JobError::Other("could not calculate checksum of directory".into(), e.into()))?.to_hex_lowercase())
         } else {
             let handle = File::open(file).map_err(|e| JobError::Other("could not open file to calculate checksum".into(), e.into()))?;
-            chksum::<SHA1, _>(handle).map_err(|e| JobError::Other("could not calculate checksum".into(), e.into()))?.to_hex_lowercase()
-        };
+            Ok(chksum::<SHA1, _>(handle).map_err(|e| JobError::Other("could not calculate checksum".into(), e.into()))?.to_hex_lowercase())
+        }
+    }
 
-        Ok(Self { path, checksum })
+    /// returns the relative path of the resource
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     /// checks the checksum compared to the new one, returns true if a change was detected
-    pub fn changed(&self, parent: &Path) -> bool {
-        let mut file = parent.to_owned();
-        file.push(&self.path);
-
-        File::open(file).context("failed to read file for checksum")
-            .and_then(|f| chksum::<SHA1, _>(f).context("failed to calculate checksum"))
-            .map(|c| c.to_hex_lowercase() != self.checksum)
+    pub fn changed(&self, env: &JobEnvironment) -> bool {
+        Self::checksum(&env.resolve(&self.path))
+            .map(|c| c != self.checksum)
             .unwrap_or(true)
     }
 
@@ -65,7 +138,7 @@ impl ResourceItem {
 type JobResult<T> = Result<T, JobError>;
 
 pub enum JobError {
-    Variable(VariableError, String, PathBuf),
+    Variable(Box<VariableError>, String, PathBuf),
     Resources(PathBuf, Error),
     Other(String, anyhow::Error)
 }
@@ -76,6 +149,14 @@ pub struct Job {
     /// Title of the job, if none, one will be generated
     title: Option<String>,
 
+    /// Stable identifier of the job inside its module
+    id: Option<String>,
+    /// Identifiers of jobs of the same module this job is installed after
+    after: Option<Vec<String>>,
+
+    /// Condition as a variable expression, the job is only installed if it evaluates to true
+    when: Option<String>,
+
     /// The actual function of the job
     #[schemars(with = "types::JobTypes")]
     job: Box<dyn Installable>
@@ -88,26 +169,103 @@ impl Job {
         self.title.clone().unwrap_or_else(|| self.job.construct_title())
     }
 
+    /// Returns the identifier of the job
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Returns whether this job replaces the other one when inheriting, which is the case if both have the same id or explicit title
+    pub fn overrides(&self, other: &Job) -> bool {
+        match (&self.id, &other.id) {
+            (Some(id), Some(other)) => id == other,
+            _ => self.title.is_some() && self.title == other.title
+        }
+    }
+
+    /// Evaluates the condition of the job, marking the used variables on the given build
+    fn applies(&self, env: &JobEnvironment, built: &mut BuiltJob) -> Result<bool, JobError> {
+        match &self.when {
+            Some(condition) => helper::process_condition(condition, env, built),
+            None => Ok(true)
+        }
+    }
+
     pub fn build(&self, env: &JobEnvironment) -> Result<BuiltJob, JobError> {
-        let mut built = self.job.build(env)?;
+        let mut condition = BuiltJob::new();
+
+        // jobs whose condition is false are built empty, so they still record the variables of the condition
+        let mut built = if self.applies(env, &mut condition)? {
+            self.job.build(env)?
+        } else {
+            BuiltJob::new()
+        };
 
         // change generic attributes
         built.title = self.title();
+        built.ordered = self.after.as_ref().is_some_and(|a| !a.is_empty());
+        built.variables.append(&mut condition.variables);
 
         Ok(built)
     }
 
     pub fn partial(&self, old: &Job, previous: &BuiltJob, env: &JobEnvironment) -> Option<Result<BuiltJob, JobError>>{
+        let mut condition = BuiltJob::new();
+
+        // only do partial builds if the job was and still is applied
+        match self.applies(env, &mut condition) {
+            Ok(true) if !previous.changes.is_empty() => {}
+            Ok(_) => return None,
+            Err(e) => return Some(Err(e))
+        }
+
         let mut built = self.job.partial(old.job.as_ref(), previous, env)?;
 
         if let Ok(job) = &mut built {
             job.title = self.title();
+            job.ordered = self.after.as_ref().is_some_and(|a| !a.is_empty());
+            job.variables.append(&mut condition.variables);
         }
 
         Some(built)
     }
 }
 
+/// Orders jobs so every job comes after the jobs it is declared to be installed after, otherwise keeping the declaration order
+pub fn order_jobs(jobs: Vec<Job>) -> anyhow::Result<Vec<Job>> {
+    // validate ids and references
+    for (index, job) in jobs.iter().enumerate() {
+        if let Some(id) = &job.id {
+            if jobs[..index].iter().any(|j| j.id.as_ref() == Some(id)) {
+                return Err(anyhow!("Job id '{id}' is used by multiple jobs"));
+            }
+        }
+
+        for after in job.after.iter().flatten() {
+            if !jobs.iter().any(|j| j.id.as_ref() == Some(after)) {
+                return Err(anyhow!("Job '{}' is declared after the job '{after}', which does not exist", job.title()));
+            }
+        }
+    }
+
+    let mut remaining = jobs;
+    let mut ordered: Vec<Job> = Vec::with_capacity(remaining.len());
+
+    // always take the first job whose predecessors are all placed
+    while !remaining.is_empty() {
+        let next = remaining.iter().position(|job| {
+            job.after.iter().flatten().all(|after| ordered.iter().any(|j| j.id.as_ref() == Some(after)))
+        });
+
+        match next {
+            Some(index) => ordered.push(remaining.remove(index)),
+            None => return Err(anyhow!("Jobs {} are declared after each other in a cycle",
+                remaining.iter().map(|j| format!("'{}'", j.title())).collect::<Vec<_>>().join(", ")))
+        }
+    }
+
+    Ok(ordered)
+}
+
 /// this struct contains all information about a built job
 #[derive(Serialize, Deserialize, Clone)]
 pub struct BuiltJob {
@@ -119,6 +277,12 @@ pub struct BuiltJob {
 
     /// changes to be made to the system
     pub changes: Vec<Box<dyn AtomicChange>>,
+    /// changes made when other jobs of the module changed, whilst this job stayed the same
+    #[serde(default)]
+    pub refresh: Vec<Box<dyn AtomicChange>>,
+    /// is the job declared to be installed after other jobs of its module
+    #[serde(default)]
+    pub ordered: bool,
 
     /// resources on which the job depends
     pub resources: Vec<ResourceItem>,
@@ -134,6 +298,8 @@ impl BuiltJob {
             title: "unknown job".to_string(),
             root: false,
             changes: vec![],
+            refresh: vec![],
+            ordered: false,
             resources: vec![],
             variables: vec![]
         }
@@ -163,4 +329,33 @@ impl BuiltJob {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::jobs::{order_jobs, Job};
+
+    fn job(id: &str, after: &[&str]) -> Job {
+        serde_yaml::from_str(&format!("id: {id}\nafter: [{}]\njob:\n  type: command\n  install: echo {id}", after.join(", "))).expect("job should deserialize")
+    }
+
+    fn ids(jobs: Vec<Job>) -> Vec<String> {
+        jobs.into_iter().map(|j| j.id.expect("test jobs have ids")).collect()
+    }
+
+    /// tests that jobs are ordered after their predecessors, keeping the declaration order otherwise
+    #[test]
+    fn order() {
+        let jobs = vec![job("a", &["c"]), job("b", &[]), job("c", &[]), job("d", &["a", "b"])];
+        assert_eq!(ids(order_jobs(jobs).unwrap()), vec!["b", "c", "a", "d"]);
 
+        let jobs = vec![job("a", &[]), job("b", &[]), job("c", &[])];
+        assert_eq!(ids(order_jobs(jobs).unwrap()), vec!["a", "b", "c"]);
+    }
+
+    /// tests that invalid orderings are rejected
+    #[test]
+    fn order_invalid() {
+        assert!(order_jobs(vec![job("a", &["b"]), job("b", &["a"])]).is_err());
+        assert!(order_jobs(vec![job("a", &["x"])]).is_err());
+        assert!(order_jobs(vec![job("a", &[]), job("a", &[])]).is_err());
+    }
+}
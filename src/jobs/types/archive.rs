@@ -0,0 +1,52 @@
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobError, JobResult};
+use crate::jobs::helper::resource_mark;
+use crate::module::change::{ClearChange, ExtractChange};
+use crate::module::change::archive::{ArchiveFormat, entries};
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct ArchiveJob {
+    archive: String,
+    location: String,
+
+    root: Option<bool>
+}
+
+#[typetag::serde(name = "archive")]
+impl Installable for ArchiveJob {
+    fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+
+        // Get source archive
+        let source = PathBuf::from(shellexpand::tilde(&self.archive).as_ref());
+        let path = resource_mark(&source, env, &mut built)?;
+
+        let format = ArchiveFormat::detect(&path)
+            .ok_or_else(|| JobError::Resources(source.clone(), Error::new(ErrorKind::InvalidInput, "unsupported archive format, expected .tar.gz, .tar.zst or .zip")))?;
+        let entries = entries(&path, format).map_err(|e| JobError::Resources(source.clone(), e))?;
+
+        // Prepare the locations of the top level entries
+        let target = PathBuf::from(shellexpand::tilde(&self.location).as_ref());
+        for entry in entries.iter().filter(|e| e.components().count() == 1) {
+            built.change(Box::new(ClearChange::new(target.join(entry), false)));
+        }
+
+        built.change(Box::new(ExtractChange::new(path, target, entries)));
+
+        built.root = self.root.unwrap_or_default();
+
+        Ok(built)
+    }
+
+    fn partial(&self, _old: &dyn Installable, _previous: &BuiltJob, _env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        // always reinstall, so entries missing in the new archive are removed
+        None
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Extracting the archive '{}' to its target location", self.archive)
+    }
+}
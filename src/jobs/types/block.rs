@@ -0,0 +1,79 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobError, JobResult};
+use crate::jobs::helper::{process_variables, resource_load};
+use crate::module::change::{BlockChange, DirectoryChange};
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct BlockJob {
+    content: Option<String>,
+    file: Option<String>,
+    location: String,
+
+    marker: String,
+    comment: Option<String>,
+
+    root: Option<bool>
+}
+
+impl BlockJob {
+
+    /// Returns the lines which delimit the block
+    fn markers(&self) -> (String, String) {
+        let comment = self.comment.as_deref().unwrap_or("#");
+
+        (format!("{comment} BEGIN pusta {}", self.marker), format!("{comment} END pusta {}", self.marker))
+    }
+}
+
+#[typetag::serde(name = "block")]
+impl Installable for BlockJob {
+    fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+
+        // Load content of the block
+        let text = match (&self.content, &self.file) {
+            (Some(content), None) => process_variables(content, Path::new("block-content"), env, &mut built)?,
+            (None, Some(file)) => {
+                let source = PathBuf::from(file);
+
+                let resource = resource_load(&source, env, &mut built)?;
+                process_variables(&resource, &source, env, &mut built)?
+            }
+            _ => {
+                return Err(JobError::Other("the block job is invalid".into(), Error::new(ErrorKind::InvalidInput, "exactly one of content or file must be set").into()))
+            }
+        };
+
+        // Write block to file
+        let target = PathBuf::from(shellexpand::tilde(&self.location).as_ref());
+        if let Some(parent) = target.parent() {
+            built.change(Box::new(DirectoryChange::new(parent.to_owned())));
+        }
+
+        let (begin, end) = self.markers();
+        built.change(Box::new(BlockChange::new(target, begin, end, text)));
+
+        built.root = self.root.unwrap_or_default();
+
+        Ok(built)
+    }
+
+    fn partial(&self, old: &dyn Installable, _previous: &BuiltJob, env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        let old = old.as_any().downcast_ref::<Self>()?;
+
+        // remove the old block if it is somewhere else
+        if self.location != old.location || self.markers() != old.markers() {
+            return None;
+        }
+
+        // the block is replaced in place
+        Some(self.build(env))
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Writing the block '{}' into '{}'", self.marker, self.location)
+    }
+}
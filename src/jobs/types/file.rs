@@ -1,26 +1,106 @@
-use std::path::{PathBuf};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobResult};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobError, JobResult};
 use crate::jobs::helper::{process_variables, resource_dir, resource_load, resource_mark};
-use crate::module::change::{ClearChange, CopyChange, LinkChange, WriteChange};
+use crate::module::change::{AttributeChange, ClearChange, CopyChange, DirectoryChange, LinkChange, RestoreChange, WriteChange};
 
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
 pub struct FileJob {
-    file: String,
+    file: Option<String>,
+    files: Option<String>,
     location: String,
     permissions: Option<u32>,
+    owner: Option<Principal>,
+    group: Option<Principal>,
 
     root: Option<bool>,
-    link: Option<bool>
+    link: Option<bool>,
+    template: Option<bool>
+}
+
+/// A user or group, given by name or id
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum Principal {
+    Id(u32),
+    Name(String)
+}
+
+impl Principal {
+    fn value(&self) -> String {
+        match self {
+            Principal::Id(id) => id.to_string(),
+            Principal::Name(name) => name.clone()
+        }
+    }
 }
 
 impl FileJob {
 
+    /// Returns the expanded target location
+    fn location(&self) -> PathBuf {
+        PathBuf::from(shellexpand::tilde(&self.location).as_ref())
+    }
+
+    /// Returns the leading part of a pattern without wildcards, matches are placed relative to it
+    fn base(pattern: &str) -> PathBuf {
+        Path::new(pattern).components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+            .collect()
+    }
+
+    /// Collects the source files and their target locations
+    fn targets(&self, env: &JobEnvironment) -> JobResult<Vec<(PathBuf, PathBuf)>> {
+        let location = self.location();
+
+        match (&self.file, &self.files) {
+            (Some(file), None) => Ok(vec![(PathBuf::from(file), location)]),
+            (None, Some(pattern)) => {
+                let base = Self::base(pattern);
+
+                Ok(Self::matches(pattern, env)?.into_iter()
+                    .map(|source| {
+                        let target = location.join(source.strip_prefix(&base).unwrap_or(&source));
+                        (source, target)
+                    })
+                    .collect())
+            }
+            _ => Err(JobError::Other("the file job is invalid".into(), Error::new(ErrorKind::InvalidInput, "exactly one of file or files must be set").into()))
+        }
+    }
+
+    /// Finds all files matching a glob pattern in the resource directories
+    fn matches(pattern: &str, env: &JobEnvironment) -> JobResult<BTreeSet<PathBuf>> {
+        let mut found = BTreeSet::new();
+
+        for dir in &env.resources {
+            let full = format!("{}/{pattern}", glob::Pattern::escape(&dir.to_string_lossy()));
+            let paths = glob::glob(&full)
+                .map_err(|e| JobError::Other(format!("the pattern '{pattern}' is invalid"), e.into()))?;
+
+            for path in paths.flatten().filter(|p| p.is_file()) {
+                if let Ok(relative) = path.strip_prefix(dir) {
+                    found.insert(relative.to_owned());
+                }
+            }
+        }
+
+        // a pattern without matches is most likely a mistake
+        if found.is_empty() {
+            return Err(JobError::Other(format!("the pattern '{pattern}' does not match any files"), Error::new(ErrorKind::NotFound, "no resource file matches the pattern").into()));
+        }
+
+        Ok(found)
+    }
+
     /// Deploys the file to the optimal location
-    fn deploy(&self, target: PathBuf, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<()>{
-        // Get source file
-        let source = PathBuf::from(&self.file);
+    fn deploy(&self, source: PathBuf, target: PathBuf, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<()>{
+        // Check source file
         let is_dir = resource_dir(&source, env)?;
 
         // deploy file depending on link and dir status
@@ -29,17 +109,88 @@ impl FileJob {
                 let resource = resource_load(&source, env, built)?;
                 let resource = process_variables(&resource, &source, env, built)?;
 
-                built.change(Box::new(WriteChange::new(resource, self.permissions.unwrap_or(0o0644), target)));
+                built.change(Box::new(WriteChange::new(resource, self.permissions.unwrap_or(0o0644), target.clone())));
+            },
+            (false, true) if self.template.unwrap_or_default() => {
+                // the directory is marked too, so added files are detected
+                resource_mark(&source, env, built)?;
+
+                built.change(Box::new(DirectoryChange::owned(target.clone())));
+                self.deploy_template(&source, &target, env, built)?;
             },
             (false, true) => {
                 let path = resource_mark(&source, env, built)?;
 
-                built.change(Box::new(CopyChange::new(target, path)));
+                built.change(Box::new(CopyChange::new(target.clone(), path)));
+
+                // copied directories get their modes recursively
+                if self.permissions.is_some() {
+                    built.change(Box::new(AttributeChange::new(target.clone(), None, None, self.permissions)));
+                }
             },
             (true, _) => {
                 let path = resource_mark(&source, env, built)?;
 
-                built.change(Box::new(LinkChange::new(target, path)));
+                built.change(Box::new(LinkChange::new(target.clone(), path)));
+            }
+        }
+
+        // set ownership as root
+        if self.owner.is_some() || self.group.is_some() {
+            built.change(Box::new(AttributeChange::new(target,
+                                                       self.owner.as_ref().map(Principal::value),
+                                                       self.group.as_ref().map(Principal::value),
+                                                       None)));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the job has to run as root
+    fn root(&self) -> bool {
+        self.root.unwrap_or_default() || self.owner.is_some() || self.group.is_some()
+    }
+
+    /// Renders every file of a directory with variables and writes it to the target
+    fn deploy_template(&self, source: &Path, target: &Path, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<()> {
+        let directory = env.resolve(source);
+
+        let mut entries = fs::read_dir(&directory)
+            .and_then(|d| d.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| JobError::Resources(source.to_owned(), e))?;
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let file = source.join(entry.file_name());
+            let location = target.join(entry.file_name());
+
+            if entry.path().is_dir() {
+                built.change(Box::new(DirectoryChange::owned(location.clone())));
+                self.deploy_template(&file, &location, env, built)?;
+            } else {
+                // keep the mode of every single file, if none is set
+                let permissions = match self.permissions {
+                    Some(permissions) => permissions,
+                    None => fs::metadata(entry.path()).map_err(|e| JobError::Resources(file.clone(), e))?.permissions().mode() & 0o7777
+                };
+
+                // binary files cannot contain variables and are copied as they are
+                let content = fs::read(entry.path()).map_err(|e| JobError::Resources(file.clone(), e))?;
+                if std::str::from_utf8(&content).is_err() {
+                    let path = resource_mark(&file, env, built)?;
+
+                    built.change(Box::new(CopyChange::new(location.clone(), path)));
+                    if self.permissions.is_some() {
+                        built.change(Box::new(AttributeChange::new(location, None, None, self.permissions)));
+                    }
+
+                    continue;
+                }
+
+                let resource = resource_load(&file, env, built)?;
+                let resource = process_variables(&resource, &file, env, built)?;
+
+                built.change(Box::new(WriteChange::new(resource, permissions, location)));
             }
         }
 
@@ -52,14 +203,25 @@ impl Installable for FileJob {
     fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
         let mut built = BuiltJob::new();
 
-        // Get and prepare location
-        let target = PathBuf::from(shellexpand::tilde(&self.location).as_ref());
-        built.change(Box::new(ClearChange::new(target.clone(), false)));
+        // links point to the resources, so they cannot be rendered
+        if self.link.unwrap_or_default() && self.template.unwrap_or_default() {
+            return Err(JobError::Other("the file job is invalid".into(), Error::new(ErrorKind::InvalidInput, "link and template cannot be combined").into()));
+        }
 
-        // deploy file to location
-        self.deploy(target, env, &mut built)?;
+        for (source, target) in self.targets(env)? {
+            // Get and prepare location
+            built.change(Box::new(ClearChange::new(target.clone(), false)));
 
-        built.root = self.root.unwrap_or_default();
+            // deploy file to location
+            self.deploy(source, target, env, &mut built)?;
+        }
+
+        // the base directory is marked too, so added matches are detected
+        if let Some(pattern) = &self.files {
+            resource_mark(&Self::base(pattern), env, &mut built)?;
+        }
+
+        built.root = self.root();
 
         Ok(built)
     }
@@ -67,33 +229,98 @@ impl Installable for FileJob {
     fn partial(&self, old: &dyn Installable, previous: &BuiltJob, env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
         let old = old.as_any().downcast_ref::<Self>()?;
 
-        // reinstall whole if location changed
-        if self.location != old.location {
+        // reinstall whole if location changed, or templated files could be left behind
+        if self.location != old.location || self.files.is_some() != old.files.is_some() ||
+            self.template.unwrap_or_default() || old.template.unwrap_or_default() {
             return None;
         }
 
         // update installation
         let mut built = BuiltJob::new();
 
-        // prepare location but keep cache
-        let target = PathBuf::from(shellexpand::tilde(&self.location).as_ref());
-        built.change(Box::new(ClearChange::new(target.clone(), true)));
+        let targets = match self.targets(env) {
+            Ok(targets) => targets,
+            Err(e) => return Some(Err(e))
+        };
+
+        // matched files of the previous installation are its resources, besides the base directory
+        let previous_base = old.files.as_deref().map(Self::base);
+        let previous_sources: BTreeSet<&Path> = previous.resources.iter()
+            .map(|r| r.path())
+            .filter(|p| Some(*p) != previous_base.as_deref())
+            .collect();
+
+        for (source, target) in &targets {
+            // prepare location but keep cache, if it was installed before
+            let inherit = self.files.is_none() || previous_sources.contains(source.as_path());
+            built.change(Box::new(ClearChange::new(target.clone(), inherit)));
+
+            // deploy file to location
+            if let Err(e) = self.deploy(source.clone(), target.clone(), env, &mut built) {
+                return Some(Err(e))
+            }
+        }
 
-        // deploy file to location
-        if let Err(e) = self.deploy(target, env, &mut built) {
-            return Some(Err(e))
+        if let Some(pattern) = &self.files {
+            if let Err(e) = resource_mark(&Self::base(pattern), env, &mut built) {
+                return Some(Err(e))
+            }
         }
 
-        built.root = self.root.unwrap_or_default();
+        // remove files which are no longer matched
+        if let Some(pattern) = &old.files {
+            let base = Self::base(pattern);
+
+            for source in previous_sources.iter().filter(|s| !targets.iter().any(|(t, _)| t == *s)) {
+                let target = old.location().join(source.strip_prefix(&base).unwrap_or(source));
+                built.change(Box::new(RestoreChange::new(target)));
+            }
+        }
+
+        built.root = self.root();
 
         Some(Ok(built))
     }
 
     fn construct_title(&self) -> String {
         let action = self.link.unwrap_or(false);
-        format!("{} the file '{}' to its target location",
-                if action { "Linking" } else { "Copying" },
-                &self.file
-        )
+        match &self.files {
+            Some(files) => format!("{} the files '{files}' to their target location",
+                                   if action { "Linking" } else { "Copying" }),
+            None => format!("{} the file '{}' to its target location",
+                            if action { "Linking" } else { "Copying" },
+                            self.file.as_deref().unwrap_or_default())
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use crate::jobs::{Installable, TestDir};
+    use crate::jobs::types::file::FileJob;
+
+    #[test]
+    fn template_binary() {
+        let dir = TestDir::new("template");
+        let (env, runtime) = (dir.env(), dir.runtime());
+
+        let image = [0x89, b'P', b'N', b'G', 0xff, 0xfe, 0x00, 0x01];
+        fs::create_dir_all(dir.path.join("theme")).unwrap();
+        fs::write(dir.path.join("theme/icon.png"), image).unwrap();
+        fs::write(dir.path.join("theme/colors.conf"), "background = black\n").unwrap();
+
+        let job: FileJob = serde_yaml::from_str(&format!("file: theme\nlocation: {}\ntemplate: true", dir.path.join("target").display())).unwrap();
+        let Ok(built) = job.build(&env) else { panic!("the job should build") };
+
+        for change in &built.changes {
+            change.apply(&runtime).unwrap();
+        }
+
+        assert_eq!(fs::read(dir.path.join("target/icon.png")).unwrap(), image);
+        assert_eq!(fs::read_to_string(dir.path.join("target/colors.conf")).unwrap(), "background = black\n");
+
+        // links cannot be templated
+        let job: FileJob = serde_yaml::from_str("file: theme\nlocation: /tmp\nlink: true\ntemplate: true").unwrap();
+        assert!(job.build(&env).is_err());
     }
-}
\ No newline at end of file
+}
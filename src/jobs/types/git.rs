@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobResult};
+use crate::module::change::{ClearChange, GitChange};
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct GitJob {
+    repository: String,
+    location: String,
+    #[serde(rename = "ref")]
+    reference: Option<String>,
+
+    show_output: Option<bool>,
+    root: Option<bool>
+}
+
+impl GitJob {
+
+    /// Adds the changes to clone or update the repository
+    fn deploy(&self, update: bool, built: &mut BuiltJob) {
+        let target = PathBuf::from(shellexpand::tilde(&self.location).as_ref());
+
+        // prepare location, keeping the cache when updating
+        built.change(Box::new(ClearChange::new(target.clone(), update)));
+        built.change(Box::new(GitChange::new(self.repository.clone(), target, self.reference.clone(), update, self.show_output.unwrap_or_default())));
+
+        built.root = self.root.unwrap_or_default();
+    }
+}
+
+#[typetag::serde(name = "git")]
+impl Installable for GitJob {
+    fn build(&self, _env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+        self.deploy(false, &mut built);
+
+        Ok(built)
+    }
+
+    fn partial(&self, old: &dyn Installable, _previous: &BuiltJob, _env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        let old = old.as_any().downcast_ref::<Self>()?;
+
+        // clone again if the repository or location changed
+        if self.repository != old.repository || self.location != old.location {
+            return None;
+        }
+
+        // fetch and checkout in existing clone
+        let mut built = BuiltJob::new();
+        self.deploy(true, &mut built);
+
+        Some(Ok(built))
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Cloning the git repository '{}'", self.repository)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use crate::jobs::{BuiltJob, Installable, TestDir};
+    use crate::jobs::types::git::GitJob;
+    use crate::module::change::ChangeRuntime;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=pusta", "-c", "user.email=pusta@localhost", "-c", "commit.gpgsign=false"])
+            .args(args).current_dir(dir)
+            .output().expect("git should be installed").status;
+        assert!(status.success(), "git {} failed", args.join(" "));
+    }
+
+    fn job(url: &str, location: &Path, reference: &str) -> GitJob {
+        serde_yaml::from_str(&format!("repository: {url}\nlocation: {}\nref: {reference}", location.display())).unwrap()
+    }
+
+    fn apply(built: &BuiltJob, runtime: &ChangeRuntime) {
+        for change in &built.changes {
+            change.apply(runtime).unwrap();
+        }
+    }
+
+    fn revert(built: &BuiltJob, runtime: &ChangeRuntime) {
+        for change in built.changes.iter().rev() {
+            change.revert(runtime).unwrap();
+        }
+    }
+
+    #[test]
+    fn clone_update_revert() {
+        let dir = TestDir::new("git");
+        let (env, runtime) = (dir.env(), dir.runtime());
+
+        // a remote with a tagged first version and a newer commit on main
+        let work = dir.path.join("work");
+        fs::create_dir_all(&work).unwrap();
+        git(&work, &["init", "--quiet", "-b", "main"]);
+        fs::write(work.join("version"), "1").unwrap();
+        git(&work, &["add", "version"]);
+        git(&work, &["commit", "--quiet", "-m", "first"]);
+        git(&work, &["tag", "v1"]);
+        fs::write(work.join("version"), "2").unwrap();
+        git(&work, &["commit", "--quiet", "-am", "second"]);
+        git(&dir.path, &["clone", "--quiet", "--bare", "work", "remote.git"]);
+
+        let url = format!("file://{}", dir.path.join("remote.git").display());
+
+        // clone at the tag
+        let target = dir.path.join("clone");
+        let first = job(&url, &target, "v1");
+        let Ok(installed) = first.build(&env) else { panic!("the job should build") };
+        apply(&installed, &runtime);
+        assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "1");
+
+        // update to the branch inside the existing clone
+        let second = job(&url, &target, "main");
+        let Some(Ok(updated)) = second.partial(&first, &installed, &env) else { panic!("the job should update") };
+        fs::write(target.join("untracked"), "kept").unwrap();
+        apply(&updated, &runtime);
+        assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "2");
+        assert!(target.join("untracked").exists());
+
+        // reverting removes the clone
+        revert(&updated, &runtime);
+        assert!(!target.exists());
+
+        // reverting restores a directory which was there before
+        let existing = dir.path.join("existing");
+        fs::create_dir_all(&existing).unwrap();
+        fs::write(existing.join("file"), "original").unwrap();
+
+        let Ok(replaced) = job(&url, &existing, "v1").build(&env) else { panic!("the job should build") };
+        apply(&replaced, &runtime);
+        assert_eq!(fs::read_to_string(existing.join("version")).unwrap(), "1");
+
+        revert(&replaced, &runtime);
+        assert_eq!(fs::read_to_string(existing.join("file")).unwrap(), "original");
+        assert!(!existing.join("version").exists());
+    }
+}
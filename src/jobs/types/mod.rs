@@ -9,6 +9,13 @@ pub mod package;
 pub mod file;
 pub mod script;
 pub mod command;
+pub mod git;
+pub mod archive;
+pub mod block;
+pub mod patch;
+pub mod stow;
+pub mod service;
+pub mod timer;
 
 #[allow(dead_code)]
 #[derive(Serialize, JsonSchema)]
@@ -21,7 +28,21 @@ pub enum JobTypes {
     /// Script job
     Script(script::ScriptJob),
     /// Command job
-    Command(command::CommandJob)
+    Command(command::CommandJob),
+    /// Git job
+    Git(git::GitJob),
+    /// Archive job
+    Archive(archive::ArchiveJob),
+    /// Block job
+    Block(block::BlockJob),
+    /// Patch job
+    Patch(patch::PatchJob),
+    /// Stow job
+    Stow(stow::StowJob),
+    /// Service job
+    Service(service::ServiceJob),
+    /// Timer job
+    Timer(timer::TimerJob)
 }
 
 // Has to be cloned during the install process creating a new installed module and also needs to be compared
@@ -1,18 +1,67 @@
-use dyn_eq::DynEq;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobResult};
-use crate::module::change::RunChange;
+use crate::config::ConfigPackage;
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobError, JobResult};
+use crate::module::change::PackageChange;
+use crate::variables::Variable;
+
+/// Key of the package names used if no other key matches
+const DEFAULT_KEY: &str = "default";
 
 /// This job installs a package from the system
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
 pub struct PackageJob {
-    names: String
+    names: PackageNames,
+    manager: Option<String>
+}
+
+/// Names of packages, either the same everywhere or per package manager or operating system
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum PackageNames {
+    All(String),
+    Mapped(BTreeMap<String, String>)
 }
 
 impl PackageJob {
-    fn name_vec(&self) -> Vec<String> {
-        self.names.split(' ').map(|s| s.to_owned()).collect()
+    /// Finds the package manager this job uses
+    fn manager<'a>(&self, env: &'a JobEnvironment) -> JobResult<&'a ConfigPackage> {
+        env.package_manager(self.manager.as_deref())
+            .ok_or_else(|| JobError::Other("the package manager is not configured".into(),
+                                           Error::new(ErrorKind::NotFound, format!("there is no package manager named '{}' in the config", self.manager.as_deref().unwrap_or_default())).into()))
+    }
+
+    /// Selects the names for this system, by package manager, then operating system, then the default
+    fn name_vec(&self, env: &JobEnvironment) -> JobResult<Vec<String>> {
+        let names = match &self.names {
+            PackageNames::All(names) => names,
+            PackageNames::Mapped(map) => {
+                let keys = self.keys(env)?;
+
+                keys.iter()
+                    .find_map(|key| map.get(key))
+                    .ok_or_else(|| JobError::Other("the package job has no names for this system".into(),
+                                                   Error::new(ErrorKind::NotFound, format!("none of the keys {} is present",
+                                                                                           keys.iter().map(|k| format!("'{k}'")).collect::<Vec<_>>().join(", "))).into()))?
+            }
+        };
+
+        Ok(names.split(' ').filter(|s| !s.is_empty()).map(|s| s.to_owned()).collect())
+    }
+
+    /// Returns the keys names are looked up by, in order of precedence.
+    /// These are the key of a named package manager, the name of the package manager, the operating system and the default key.
+    fn keys(&self, env: &JobEnvironment) -> JobResult<Vec<String>> {
+        let mut keys = vec![];
+        if let Some(manager) = &self.manager { keys.push(manager.clone()); }
+        if let Some(name) = &self.manager(env)?.name { keys.push(name.clone()); }
+        if let Some(Variable::Value(os)) = env.variables.find("pusta.os") { keys.push(os.to_string()); }
+        keys.push(DEFAULT_KEY.to_owned());
+
+        keys.dedup();
+        Ok(keys)
     }
 }
 
@@ -22,48 +71,63 @@ impl Installable for PackageJob {
     fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
         let mut built = BuiltJob::new();
 
-        let names = self.name_vec();
-        built.change(Box::new(RunChange::new(
-            env.package_config.create_install(&names),
-            Some(env.package_config.create_remove(&names)),
-            env.path.clone(), true, false)));
+        // no packages are needed on this system
+        let names = self.name_vec(env)?;
+        if names.is_empty() {
+            return Ok(built);
+        }
 
-        built.root = env.package_config.root;
+        let manager = self.manager(env)?;
+        built.change(Box::new(PackageChange::new(manager.clone(), names, env.path.clone())));
+
+        built.root = manager.root;
 
         Ok(built)
     }
 
-    fn partial(&self, old: &dyn Installable, previous: &BuiltJob, env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
-        let old = old.as_any().downcast_ref::<Self>()?;
-
-        // Compare packages
-        let old = old.name_vec();
-        let new = self.name_vec();
-
-        let remove: Vec<String> = old.iter().filter(|s| !new.contains(*s)).cloned().collect();
-        let install: Vec<String> = new.iter().filter(|s| !old.contains(*s)).cloned().collect();
-
-        let mut built = BuiltJob::new();
+    fn partial(&self, _old: &dyn Installable, _previous: &BuiltJob, _env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        // packages kept by the new job are neither removed nor installed again, as the package changes are batched
+        None
+    }
 
-        // remove removed modules
-        if !remove.is_empty() {
-            built.change(Box::new(RunChange::new(
-                env.package_config.create_remove(&remove),
-                None, env.path.clone(), true, false)));
+    fn construct_title(&self) -> String {
+        let join = |names: &str| names.split(' ').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("', '");
+
+        let target = match &self.manager {
+            Some(manager) => format!("with {manager}"),
+            None => "on the system".to_owned()
+        };
+
+        match &self.names {
+            PackageNames::All(names) => format!("Installing the package(s) '{}' {target}", join(names)),
+            PackageNames::Mapped(map) => match map.get(DEFAULT_KEY) {
+                Some(names) => format!("Installing the package(s) '{}' or their system specific names {target}", join(names)),
+                None => format!("Installing system specific package(s) {target}")
+            }
         }
+    }
+}
 
-        // install new modules
-        built.change(Box::new(RunChange::new(
-            env.package_config.create_install(&install),
-            Some(env.package_config.create_remove(&new)),
-            env.path.clone(), true, false)));
+#[cfg(test)]
+mod test {
+    use crate::config::ConfigPackage;
+    use crate::jobs::TestDir;
+    use crate::jobs::types::package::PackageJob;
 
-        built.root = env.package_config.root;
+    #[test]
+    fn names() {
+        let dir = TestDir::new("package");
+        let mut env = dir.env();
+        env.package_config = ConfigPackage { name: Some("pacman".into()), ..Default::default() };
+        env.package_managers.insert("flatpak".into(), ConfigPackage::default());
 
-        Some(Ok(built))
-    }
+        let names = "names:\n  flatpak: com.spotify.Client\n  pacman: spotify-launcher\n  default: spotify";
 
-    fn construct_title(&self) -> String {
-        format!("Installing the package(s) '{}' on the system", self.name_vec().join("', '"))
+        // named package managers are looked up by their key, even without a name
+        let job: PackageJob = serde_yaml::from_str(&format!("{names}\nmanager: flatpak")).unwrap();
+        assert_eq!(job.name_vec(&env).ok(), Some(vec!["com.spotify.Client".to_owned()]));
+
+        let job: PackageJob = serde_yaml::from_str(names).unwrap();
+        assert_eq!(job.name_vec(&env).ok(), Some(vec!["spotify-launcher".to_owned()]));
     }
 }
@@ -0,0 +1,68 @@
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobError, JobResult};
+use crate::jobs::helper::{process_variables, resource_load};
+use crate::module::change::{DirectoryChange, PatchChange};
+use crate::module::change::patch::PatchFormat;
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct PatchJob {
+    content: Option<String>,
+    file: Option<String>,
+    location: String,
+    format: Option<PatchFormat>,
+
+    root: Option<bool>
+}
+
+#[typetag::serde(name = "patch")]
+impl Installable for PatchJob {
+    fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+
+        // Load fragment to merge
+        let (fragment, source) = match (&self.content, &self.file) {
+            (Some(content), None) => {
+                let source = PathBuf::from("patch-content");
+                (process_variables(content, &source, env, &mut built)?, source)
+            },
+            (None, Some(file)) => {
+                let source = PathBuf::from(file);
+
+                let resource = resource_load(&source, env, &mut built)?;
+                (process_variables(&resource, &source, env, &mut built)?, source)
+            }
+            _ => {
+                return Err(JobError::Other("the patch job is invalid".into(), Error::new(ErrorKind::InvalidInput, "exactly one of content or file must be set").into()))
+            }
+        };
+
+        // Parse fragment in the format of the target
+        let target = PathBuf::from(shellexpand::tilde(&self.location).as_ref());
+        let format = self.format.or_else(|| PatchFormat::detect(&target))
+            .ok_or_else(|| JobError::Other("the patch job is invalid".into(), Error::new(ErrorKind::InvalidInput, "the format could not be detected from the location, please set it").into()))?;
+
+        let fragment = format.parse(&fragment)
+            .map_err(|e| JobError::Resources(source, Error::new(ErrorKind::InvalidData, e)))?;
+
+        if let Some(parent) = target.parent() {
+            built.change(Box::new(DirectoryChange::new(parent.to_owned())));
+        }
+        built.change(Box::new(PatchChange::new(target, format, fragment)));
+
+        built.root = self.root.unwrap_or_default();
+
+        Ok(built)
+    }
+
+    fn partial(&self, _old: &dyn Installable, _previous: &BuiltJob, _env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        // always reinstall, so the previous values are restored before patching again
+        None
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Patching the file '{}'", self.location)
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::config::ConfigService;
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobResult};
+use crate::jobs::helper::process_variables;
+use crate::module::change::RunChange;
+
+/// This job enables and starts a service
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct ServiceJob {
+    service: String,
+    user: Option<bool>,
+
+    restart_on_change: Option<bool>
+}
+
+impl ServiceJob {
+
+    /// Returns the commands of the scope of the service
+    fn config<'a>(&self, env: &'a JobEnvironment) -> &'a ConfigService {
+        if self.user.unwrap_or_default() { &env.service_config.user } else { &env.service_config.system }
+    }
+}
+
+#[typetag::serde(name = "service")]
+impl Installable for ServiceJob {
+    fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+        let config = self.config(env);
+
+        let service = process_variables(&self.service, Path::new("service"), env, &mut built)?;
+
+        built.change(Box::new(RunChange::new(
+            config.create_enable(&service),
+            Some(config.create_disable(&service)),
+            env.path.clone(), false, false)));
+
+        // restart the service if the rest of the module changed
+        if self.restart_on_change.unwrap_or_default() {
+            built.refresh.push(Box::new(RunChange::new(
+                config.create_restart(&service),
+                None, env.path.clone(), false, false)));
+        }
+
+        built.root = config.root;
+
+        Ok(built)
+    }
+
+    fn partial(&self, _old: &dyn Installable, _previous: &BuiltJob, _env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        // the old service is disabled before the new one is enabled
+        None
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Enabling the {}service '{}'", if self.user.unwrap_or_default() { "user " } else { "" }, self.service)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use crate::config::{ConfigService, ConfigServices};
+    use crate::jobs::{Installable, TestDir};
+    use crate::jobs::types::service::ServiceJob;
+
+    #[test]
+    fn enable_restart_disable() {
+        let dir = TestDir::new("service");
+        let (mut env, runtime) = (dir.env(), dir.runtime());
+
+        // stub commands which only record what they were called with
+        let stub = ConfigService {
+            root: false,
+            enable: "echo enable %SERVICE% >> log".into(),
+            disable: "echo disable %SERVICE% >> log".into(),
+            restart: "echo restart %SERVICE% >> log".into()
+        };
+        env.service_config = ConfigServices { system: stub.clone(), user: stub };
+
+        let log = || fs::read_to_string(dir.path.join("log")).unwrap_or_default();
+
+        let job: ServiceJob = serde_yaml::from_str("service: syncthing.service\nuser: true\nrestart_on_change: true").unwrap();
+        let Ok(built) = job.build(&env) else { panic!("the job should build") };
+        assert!(!built.root);
+
+        for change in &built.changes { change.apply(&runtime).unwrap(); }
+        assert_eq!(log(), "enable syncthing.service\n");
+
+        // applied when another job of the module changed
+        for change in &built.refresh { change.apply(&runtime).unwrap(); }
+        assert_eq!(log(), "enable syncthing.service\nrestart syncthing.service\n");
+
+        for change in built.changes.iter().rev() { change.revert(&runtime).unwrap(); }
+        assert_eq!(log(), "enable syncthing.service\nrestart syncthing.service\ndisable syncthing.service\n");
+
+        // no restart unless requested
+        let job: ServiceJob = serde_yaml::from_str("service: syncthing.service").unwrap();
+        let Ok(built) = job.build(&env) else { panic!("the job should build") };
+        assert!(built.refresh.is_empty());
+    }
+}
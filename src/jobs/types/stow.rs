@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobResult};
+use crate::jobs::helper::{resource_files, resource_mark};
+use crate::module::change::{ClearChange, DirectoryChange, LinkChange, RestoreChange};
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct StowJob {
+    directory: String,
+    location: String,
+
+    root: Option<bool>
+}
+
+impl StowJob {
+
+    /// Returns the expanded target location
+    fn location(&self) -> PathBuf {
+        PathBuf::from(shellexpand::tilde(&self.location).as_ref())
+    }
+
+    /// Links every file of the directory, files contained in previous are expected to be linked already
+    fn deploy(&self, previous: &BTreeSet<&Path>, env: &JobEnvironment, built: &mut BuiltJob) -> JobResult<BTreeSet<PathBuf>> {
+        let directory = PathBuf::from(&self.directory);
+        let location = self.location();
+
+        let files = resource_files(&directory, env)?;
+        let relative = |file: &Path| file.strip_prefix(&directory).unwrap_or(file).to_owned();
+
+        // create intermediate directories as real directories, parents first
+        let directories: BTreeSet<PathBuf> = files.iter()
+            .flat_map(|f| relative(f).ancestors().skip(1).map(Path::to_owned).collect::<Vec<_>>())
+            .filter(|d| !d.as_os_str().is_empty())
+            .collect();
+
+        // they are removed again if nothing else is inside
+        for dir in directories {
+            built.change(Box::new(DirectoryChange::owned(location.join(dir))));
+        }
+
+        // link every file on its own
+        for file in &files {
+            let source = resource_mark(file, env, built)?;
+            let target = location.join(relative(file));
+
+            built.change(Box::new(ClearChange::new(target.clone(), previous.contains(file.as_path()))));
+            built.change(Box::new(LinkChange::new(target, source)));
+        }
+
+        // the directory is marked too, so added files are detected
+        resource_mark(&directory, env, built)?;
+
+        Ok(files)
+    }
+}
+
+#[typetag::serde(name = "stow")]
+impl Installable for StowJob {
+    fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+
+        self.deploy(&BTreeSet::new(), env, &mut built)?;
+
+        built.root = self.root.unwrap_or_default();
+
+        Ok(built)
+    }
+
+    fn partial(&self, old: &dyn Installable, previous: &BuiltJob, env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        let old = old.as_any().downcast_ref::<Self>()?;
+
+        // relink everything if the location changed
+        if self.directory != old.directory || self.location != old.location {
+            return None;
+        }
+
+        let mut built = BuiltJob::new();
+
+        // linked files of the previous installation are its resources, besides the directory
+        let previous_files: BTreeSet<&Path> = previous.resources.iter()
+            .map(|r| r.path())
+            .filter(|p| *p != Path::new(&old.directory))
+            .collect();
+
+        let files = match self.deploy(&previous_files, env, &mut built) {
+            Ok(files) => files,
+            Err(e) => return Some(Err(e))
+        };
+
+        // remove links of files which are gone
+        for file in previous_files.iter().filter(|f| !files.contains(**f)) {
+            let target = old.location().join(file.strip_prefix(&old.directory).unwrap_or(file));
+            built.change(Box::new(RestoreChange::new(target)));
+        }
+
+        built.root = self.root.unwrap_or_default();
+
+        Some(Ok(built))
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Linking every file of '{}' to its target location", self.directory)
+    }
+}
@@ -0,0 +1,283 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::config::{ConfigService, ConfigTimerScope, TimerBackend};
+use crate::jobs::{BuiltJob, Installable, JobEnvironment, JobError, JobResult};
+use crate::jobs::helper::process_variables;
+use crate::module::change::{ClearChange, CrontabChange, DirectoryChange, RunChange, WriteChange};
+
+/// Schedules which are understood by every backend
+const SCHEDULES: [&str; 5] = ["hourly", "daily", "weekly", "monthly", "yearly"];
+
+/// This job installs a command which is run on a schedule
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, JsonSchema)]
+pub struct TimerJob {
+    name: String,
+    schedule: String,
+    command: String,
+
+    user: Option<bool>
+}
+
+impl TimerJob {
+
+    /// Returns the timer configuration of the scope of the task
+    fn config<'a>(&self, env: &'a JobEnvironment) -> &'a ConfigTimerScope {
+        if self.user.unwrap_or_default() { &env.timer_config.user } else { &env.timer_config.system }
+    }
+
+    /// Returns the service commands of the scope of the task
+    fn service<'a>(&self, env: &'a JobEnvironment) -> &'a ConfigService {
+        if self.user.unwrap_or_default() { &env.service_config.user } else { &env.service_config.system }
+    }
+
+    /// Adds the changes which install a systemd service and timer
+    fn systemd(&self, command: &str, schedule: &str, env: &JobEnvironment, built: &mut BuiltJob) {
+        let config = self.config(env);
+        let service = self.service(env);
+
+        let unit = format!("pusta-{}", self.name);
+        let directory = PathBuf::from(shellexpand::tilde(&config.units).as_ref());
+
+        // reload again after the unit files were removed
+        built.change(Box::new(RunChange::new(config.reload.clone(), Some(config.reload.clone()), env.path.clone(), false, false)));
+        built.change(Box::new(DirectoryChange::owned(directory.clone())));
+
+        let service_file = directory.join(format!("{unit}.service"));
+        built.change(Box::new(ClearChange::new(service_file.clone(), false)));
+        built.change(Box::new(WriteChange::new(service_unit(&self.name, command), 0o644, service_file)));
+
+        let timer_file = directory.join(format!("{unit}.timer"));
+        built.change(Box::new(ClearChange::new(timer_file.clone(), false)));
+        built.change(Box::new(WriteChange::new(timer_unit(&self.name, schedule), 0o644, timer_file)));
+
+        built.change(Box::new(RunChange::new(config.reload.clone(), None, env.path.clone(), false, false)));
+
+        let timer = format!("{unit}.timer");
+        built.change(Box::new(RunChange::new(service.create_enable(&timer), Some(service.create_disable(&timer)), env.path.clone(), false, false)));
+    }
+
+    /// Adds the change which installs a crontab entry
+    fn cron(&self, command: &str, schedule: &str, env: &JobEnvironment, built: &mut BuiltJob) {
+        let config = self.config(env);
+
+        built.change(Box::new(CrontabChange::new(
+            config.crontab_read.clone(), config.crontab_write.clone(),
+            format!("# BEGIN pusta timer {}", self.name),
+            format!("# END pusta timer {}", self.name),
+            cron_line(schedule, command),
+            env.path.clone())));
+    }
+}
+
+#[typetag::serde(name = "timer")]
+impl Installable for TimerJob {
+    fn build(&self, env: &JobEnvironment) -> JobResult<BuiltJob> {
+        let mut built = BuiltJob::new();
+
+        // the name is part of file names and markers
+        if self.name.is_empty() || self.name.contains(|c: char| c == '/' || c.is_whitespace()) {
+            return Err(JobError::Other("the timer has an invalid name".into(),
+                                       Error::new(ErrorKind::InvalidInput, format!("'{}' must not be empty or contain slashes or whitespace", self.name)).into()));
+        }
+
+        let command = process_variables(&self.command, Path::new("command"), env, &mut built)?;
+        let command = command.trim_end_matches('\n');
+
+        // neither unit files nor crontabs support commands over multiple lines
+        if command.contains('\n') {
+            return Err(JobError::Other("the command of the timer is invalid".into(),
+                                       Error::new(ErrorKind::InvalidInput, "the command must be a single line").into()));
+        }
+
+        // the schedule must be expressible by the backend
+        let schedule = match env.timer_config.backend {
+            TimerBackend::Systemd => calendar_event(&self.schedule),
+            TimerBackend::Cron => cron_schedule(&self.schedule)
+        }.map_err(|e| JobError::Other("the schedule of the timer is invalid".into(), Error::new(ErrorKind::InvalidInput, e).into()))?;
+
+        match env.timer_config.backend {
+            TimerBackend::Systemd => self.systemd(command, &schedule, env, &mut built),
+            TimerBackend::Cron => self.cron(command, &schedule, env, &mut built)
+        }
+
+        built.root = self.config(env).root;
+
+        Ok(built)
+    }
+
+    fn partial(&self, _old: &dyn Installable, _previous: &BuiltJob, _env: &JobEnvironment) -> Option<JobResult<BuiltJob>> {
+        // the old task is removed before the new one is installed
+        None
+    }
+
+    fn construct_title(&self) -> String {
+        format!("Scheduling the {}task '{}'", if self.user.unwrap_or_default() { "user " } else { "" }, self.name)
+    }
+}
+
+/// Creates the service unit running the command
+fn service_unit(name: &str, command: &str) -> String {
+    // systemd expands specifiers and variables, and removes one level of quotes
+    let escaped = command.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%").replace('$', "$$");
+
+    format!("[Unit]\nDescription=Scheduled task '{name}' installed by pusta\n\n[Service]\nType=oneshot\nExecStart=/bin/sh -c \"{escaped}\"\n")
+}
+
+/// Creates the timer unit starting the service on the schedule
+fn timer_unit(name: &str, schedule: &str) -> String {
+    format!("[Unit]\nDescription=Timer of the scheduled task '{name}' installed by pusta\n\n[Timer]\nOnCalendar={schedule}\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n")
+}
+
+/// Creates the crontab line running the command on a validated schedule
+fn cron_line(schedule: &str, command: &str) -> String {
+    // cron treats percent signs as newlines
+    format!("{schedule} {}", command.replace('%', "\\%"))
+}
+
+/// Splits a schedule into the five fields of a cron expression, if it is one
+fn cron_fields(schedule: &str) -> Option<Vec<&str>> {
+    let fields = schedule.split_whitespace().collect::<Vec<_>>();
+
+    (fields.len() == 5 && fields.iter().all(|f| f.chars().all(|c| c.is_ascii_alphanumeric() || "*,-/".contains(c))))
+        .then_some(fields)
+}
+
+/// Returns the schedule as written into the crontab
+fn cron_schedule(schedule: &str) -> Result<String, String> {
+    if SCHEDULES.contains(&schedule) {
+        Ok(format!("@{schedule}"))
+    } else if cron_fields(schedule).is_some() {
+        Ok(schedule.to_owned())
+    } else {
+        Err(format!("'{schedule}' is neither one of {} nor five cron fields", SCHEDULES.join(", ")))
+    }
+}
+
+/// Returns the schedule as a systemd calendar event, translating cron expressions
+fn calendar_event(schedule: &str) -> Result<String, String> {
+    if schedule.is_empty() || schedule.contains('\n') {
+        return Err("the schedule must be a single line".into())
+    }
+
+    // everything else is left to systemd, which supports far more than cron
+    let Some(fields) = cron_fields(schedule) else { return Ok(schedule.to_owned()) };
+
+    let invalid = || format!("the cron expression '{schedule}' cannot be translated to a calendar event, please write it as one");
+
+    // cron runs when either the day of the month or the weekday matches, systemd only when both do
+    if fields[2] != "*" && fields[4] != "*" {
+        return Err(invalid())
+    }
+
+    let minute = calendar_field(fields[0], "00").ok_or_else(invalid)?;
+    let hour = calendar_field(fields[1], "00").ok_or_else(invalid)?;
+    let day = calendar_field(fields[2], "01").ok_or_else(invalid)?;
+    let month = calendar_field(fields[3], "01").ok_or_else(invalid)?;
+    let weekdays = calendar_weekdays(fields[4]).ok_or_else(invalid)?;
+
+    Ok(format!("{weekdays}*-{month}-{day} {hour}:{minute}:00"))
+}
+
+/// Translates a numeric cron field to a calendar event component, repetitions start at the given value
+fn calendar_field(field: &str, start: &str) -> Option<String> {
+    let number = |n: &str| n.parse::<u32>().ok().map(|n| format!("{n:02}"));
+
+    field.split(',').map(|part| {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().ok()?)),
+            None => (part, None)
+        };
+
+        let range = match (range, range.split_once('-')) {
+            ("*", _) => if step.is_some() { start.to_owned() } else { "*".to_owned() },
+            // systemd only supports repetitions from a start value, not within ranges
+            (_, Some((from, to))) if step.is_none() => format!("{}..{}", number(from)?, number(to)?),
+            (_, Some(_)) => return None,
+            (value, None) => number(value)?
+        };
+
+        Some(match step { Some(step) => format!("{range}/{step}"), None => range })
+    }).collect::<Option<Vec<_>>>().map(|parts| parts.join(","))
+}
+
+/// Translates the weekday field of cron to the weekday prefix of a calendar event
+fn calendar_weekdays(field: &str) -> Option<String> {
+    const DAYS: [&str; 8] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let day = |n: &str| n.parse::<usize>().ok().filter(|n| *n < DAYS.len());
+
+    if field == "*" {
+        return Some(String::new())
+    }
+
+    let mut days = vec![];
+    for part in field.split(',') {
+        // ranges are listed out, since systemd does not wrap around sunday
+        match part.split_once('-') {
+            Some((from, to)) => days.extend((day(from)?..=day(to)?).map(|n| DAYS[n])),
+            None => days.push(DAYS[day(part)?])
+        }
+    }
+
+    days.dedup();
+    Some(format!("{} ", days.join(",")))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use crate::config::TimerBackend;
+    use crate::jobs::{Installable, TestDir};
+    use crate::jobs::types::timer::{calendar_event, cron_line, cron_schedule, service_unit, TimerJob};
+
+    #[test]
+    fn cron() {
+        assert_eq!(cron_line(&cron_schedule("daily").unwrap(), "tldr --update"), "@daily tldr --update");
+        assert_eq!(cron_line(&cron_schedule("*/15 * * * *").unwrap(), "date +%H"), "*/15 * * * * date +\\%H");
+        assert!(cron_schedule("*-*-* 04:00:00").is_err());
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(calendar_event("daily").unwrap(), "daily");
+        assert_eq!(calendar_event("Mon..Fri *-*-* 04:00:00").unwrap(), "Mon..Fri *-*-* 04:00:00");
+        assert_eq!(calendar_event("*/15 * * * *").unwrap(), "*-*-* *:00/15:00");
+        assert_eq!(calendar_event("30 4 1,15 * *").unwrap(), "*-*-01,15 04:30:00");
+        assert_eq!(calendar_event("0 9-17 * * 1-5").unwrap(), "Mon,Tue,Wed,Thu,Fri *-*-* 09..17:00:00");
+        assert_eq!(calendar_event("0 0 * * 0,7").unwrap(), "Sun *-*-* 00:00:00");
+        assert!(calendar_event("0 0 1 * 1").is_err());
+        assert!(calendar_event("0 0 * jan *").is_err());
+        assert!(calendar_event("0 1-10/2 * * *").is_err());
+    }
+
+    #[test]
+    fn systemd_cron_schedule() {
+        let dir = TestDir::new("timer");
+        let (mut env, runtime) = (dir.env(), dir.runtime());
+
+        // stub commands, so nothing is reloaded or enabled
+        env.timer_config.backend = TimerBackend::Systemd;
+        env.timer_config.system.units = dir.path.join("units").to_string_lossy().to_string();
+        env.timer_config.system.reload = "true".into();
+        env.service_config.system.enable = "true".into();
+        env.service_config.system.disable = "true".into();
+
+        let job: TimerJob = serde_yaml::from_str("name: sync\nschedule: '*/15 * * * *'\ncommand: vdirsyncer sync").unwrap();
+        let Ok(built) = job.build(&env) else { panic!("the job should build") };
+        for change in &built.changes { change.apply(&runtime).unwrap(); }
+
+        let timer = fs::read_to_string(dir.path.join("units/pusta-sync.timer")).unwrap();
+        assert!(timer.contains("\nOnCalendar=*-*-* *:00/15:00\n"));
+
+        // an expression systemd cannot express is rejected
+        let job: TimerJob = serde_yaml::from_str("name: sync\nschedule: '0 0 1 * 1'\ncommand: vdirsyncer sync").unwrap();
+        assert!(job.build(&env).is_err());
+    }
+
+    #[test]
+    fn systemd() {
+        let unit = service_unit("backup", "restic backup \"$HOME\" --tag %d");
+        assert!(unit.contains("ExecStart=/bin/sh -c \"restic backup \\\"$$HOME\\\" --tag %%d\"\n"));
+    }
+}
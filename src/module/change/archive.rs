@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Component, Path, PathBuf};
+use flate2::read::GzDecoder;
+
+/// Formats of archives which can be extracted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarZst,
+    Zip
+}
+
+impl ArchiveFormat {
+    /// Detects the format of an archive by its file name
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") { Some(Self::TarGz) }
+        else if name.ends_with(".tar.zst") || name.ends_with(".tzst") { Some(Self::TarZst) }
+        else if name.ends_with(".zip") { Some(Self::Zip) }
+        else { None }
+    }
+}
+
+/// Opens a tar archive, decompressing it on the fly
+fn open_tar(path: &Path, format: ArchiveFormat) -> std::io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(path)?;
+
+    let reader: Box<dyn Read> = match format {
+        ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(GzDecoder::new(file))
+    };
+
+    Ok(tar::Archive::new(reader))
+}
+
+/// Makes sure an entry path stays inside the extraction directory and normalizes it
+fn sanitize(path: &Path) -> std::io::Result<PathBuf> {
+    let mut sanitized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("archive entry '{}' points outside of the target", path.to_string_lossy())))
+        }
+    }
+
+    Ok(sanitized)
+}
+
+/// Lists all paths an archive extracts to, including implicit parent directories
+pub fn entries(path: &Path, format: ArchiveFormat) -> std::io::Result<Vec<PathBuf>> {
+    let mut found = BTreeSet::new();
+
+    let mut add = |entry: PathBuf| {
+        for ancestor in entry.ancestors() {
+            if ancestor.as_os_str().is_empty() { break }
+            found.insert(ancestor.to_owned());
+        }
+    };
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+
+            for i in 0..archive.len() {
+                let file = archive.by_index(i)?;
+                let name = file.enclosed_name()
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("archive entry '{}' points outside of the target", file.name())))?;
+
+                add(sanitize(name)?);
+            }
+        }
+        _ => {
+            let mut archive = open_tar(path, format)?;
+
+            for entry in archive.entries()? {
+                add(sanitize(&entry?.path()?)?);
+            }
+        }
+    }
+
+    Ok(found.into_iter().filter(|p| !p.as_os_str().is_empty()).collect())
+}
+
+/// Extracts an archive into a directory
+pub fn extract(path: &Path, format: ArchiveFormat, directory: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            archive.extract(directory)?;
+        }
+        _ => {
+            let mut archive = open_tar(path, format)?;
+            archive.set_preserve_permissions(true);
+            archive.unpack(directory)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use crate::module::change::archive::{ArchiveFormat, entries, extract};
+
+    #[test]
+    fn detect() {
+        assert_eq!(ArchiveFormat::detect(Path::new("icons.tar.gz")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::detect(Path::new("icons.TAR.ZST")), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::detect(Path::new("fonts.zip")), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::detect(Path::new("fonts.rar")), None);
+    }
+
+    #[test]
+    fn tar_gz() {
+        let dir = std::env::temp_dir().join(format!("pusta-archive-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // build an archive without explicit directory entries
+        let path = dir.join("theme.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(fs::File::create(&path).unwrap(), Compression::default()));
+        for (name, content) in [("./theme/index.theme", "index"), ("theme/icons/a.svg", "a")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(entries(&path, ArchiveFormat::TarGz).unwrap(), vec![
+            PathBuf::from("theme"),
+            PathBuf::from("theme/icons"),
+            PathBuf::from("theme/icons/a.svg"),
+            PathBuf::from("theme/index.theme")
+        ]);
+
+        let target = dir.join("out");
+        extract(&path, ArchiveFormat::TarGz, &target).unwrap();
+        assert_eq!(fs::read_to_string(target.join("theme/icons/a.svg")).unwrap(), "a");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
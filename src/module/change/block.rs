@@ -0,0 +1,82 @@
+/// Inserts a block delimited by markers into a text, replacing the block if it is already present
+pub fn insert_block(text: &str, begin: &str, end: &str, content: &str) -> String {
+    let mut block = String::new();
+    block.push_str(begin);
+    block.push('\n');
+    block.push_str(content);
+    if !content.is_empty() && !content.ends_with('\n') { block.push('\n'); }
+    block.push_str(end);
+    block.push('\n');
+
+    match find_block(text, begin, end) {
+        Some((start, stop)) => {
+            format!("{}{block}{}", &text[..start], &text[stop..])
+        }
+        None => {
+            let mut result = text.to_owned();
+            if !result.is_empty() && !result.ends_with('\n') { result.push('\n'); }
+            result.push_str(&block);
+            result
+        }
+    }
+}
+
+/// Removes a block delimited by markers from a text, leaving everything else untouched
+pub fn remove_block(text: &str, begin: &str, end: &str) -> String {
+    match find_block(text, begin, end) {
+        Some((start, stop)) => format!("{}{}", &text[..start], &text[stop..]),
+        None => text.to_owned()
+    }
+}
+
+/// Finds the byte range of a block, including both marker lines and the newline after the end marker
+fn find_block(text: &str, begin: &str, end: &str) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+
+        match start {
+            None if trimmed == begin => start = Some(offset),
+            Some(start) if trimmed == end => return Some((start, offset + line.len())),
+            _ => {}
+        }
+
+        offset += line.len();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::module::change::block::{insert_block, remove_block};
+
+    const BEGIN: &str = "# BEGIN pusta hosts";
+    const END: &str = "# END pusta hosts";
+
+    #[test]
+    fn insert() {
+        // appended to file without trailing newline
+        assert_eq!(insert_block("a\nb", BEGIN, END, "c"), "a\nb\n# BEGIN pusta hosts\nc\n# END pusta hosts\n");
+
+        // replaced in place
+        let text = "a\n# BEGIN pusta hosts\nold\n# END pusta hosts\nb\n";
+        assert_eq!(insert_block(text, BEGIN, END, "new\n"), "a\n# BEGIN pusta hosts\nnew\n# END pusta hosts\nb\n");
+    }
+
+    #[test]
+    fn remove() {
+        let text = "a\n# BEGIN pusta hosts\nold\n# END pusta hosts\nb\n";
+        assert_eq!(remove_block(text, BEGIN, END), "a\nb\n");
+
+        // roundtrip leaves the original untouched
+        let original = "a\nb\n";
+        assert_eq!(remove_block(&insert_block(original, BEGIN, END, "c"), BEGIN, END), original);
+
+        // unterminated blocks are not touched
+        let text = "a\n# BEGIN pusta hosts\nold\n";
+        assert_eq!(remove_block(text, BEGIN, END), text);
+    }
+}
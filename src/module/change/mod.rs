@@ -1,14 +1,22 @@
-mod shell;
+pub mod shell;
 pub mod worker;
+pub mod archive;
+mod block;
+pub mod patch;
+mod ownership;
 
 use std::fs;
 use std::fs::Permissions;
+use std::io::ErrorKind;
 use std::os::unix::fs::{PermissionsExt, symlink};
 use std::path::{Path, PathBuf};
 use chksum::hash::SHA1;
 use dyn_clone::{clone_trait_object, DynClone};
 use fs_extra::dir::CopyOptions;
 use serde::{Deserialize, Serialize};
+use crate::config::ConfigPackage;
+use crate::module::change::archive::ArchiveFormat;
+use crate::module::change::patch::{PatchFormat, PreviousValue};
 
 clone_trait_object!(AtomicChange);
 
@@ -26,6 +34,12 @@ pub trait AtomicChange: DynClone {
 
     /// Returns a list of critical data used
     fn files(&self) -> Vec<(String, String)>;
+
+    /// Returns the change if it installs packages, which are installed in batches instead of one by one
+    fn package(&self) -> Option<&PackageChange> { None }
+
+    /// Returns the change mutably if it installs packages
+    fn package_mut(&mut self) -> Option<&mut PackageChange> { None }
 }
 
 const TEMP_PATH: &str = "temp";
@@ -46,6 +60,11 @@ impl ChangeRuntime {
             return Ok(())
         }
 
+        // Keep the first cache, later changes to the same path would only cache what pusta deployed before
+        if target.exists() {
+            return Ok(())
+        }
+
         copy(path, &target)
             .map_err(|e| ChangeError::cache(path.to_owned(), target, e.to_string()))?;
 
@@ -59,6 +78,25 @@ impl ChangeRuntime {
         else { None }
     }
 
+    /// Stores data belonging to a file for later restoration
+    fn cache_store(&self, path: &Path, data: &str) -> Result<(), ChangeError> {
+        let target = self.cache_dir(path);
+
+        fs::create_dir_all(&self.cache)
+            .and_then(|_| fs::write(&target, data))
+            .map_err(|e| ChangeError::cache(path.to_owned(), target, e.to_string()))
+    }
+
+    /// Takes data stored for a file out of the cache
+    fn cache_take(&self, path: &Path) -> Option<String> {
+        let target = self.cache_dir(path);
+
+        let data = fs::read_to_string(&target).ok()?;
+        let _ = fs::remove_file(&target);
+
+        Some(data)
+    }
+
     /// Creates a path reference for an inherit cache
     fn cache_dir(&self, path: &Path) -> PathBuf {
         // calculate hash for target location
@@ -257,30 +295,104 @@ impl AtomicChange for ClearChange {
     }
 }
 
+/// This change removes a previously deployed file and restores what was cached before
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RestoreChange {
+    /// File to remove and restore
+    file: PathBuf
+}
+
+impl RestoreChange {
+    pub fn new(file: PathBuf) -> Self {
+        Self { file }
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for RestoreChange {
+    fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Delete deployed file, symlinks are removed even if dangling
+        let removed = if self.file.is_symlink() { fs::remove_file(&self.file).map_err(|e| e.to_string()) }
+            else if self.file.exists() { fs_extra::remove_items(&[&self.file]).map_err(|e| e.to_string()) }
+            else { Ok(()) };
+        removed.map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to remove deployed file".into(), e))?;
+
+        // Restore original file, the cache is used up
+        if let Some(cached) = runtime.cache_load(&self.file) {
+            copy(&cached, &self.file)
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to restore original file".into(), e.to_string()))?;
+
+            fs_extra::remove_items(&[&cached])
+                .map_err(|e| ChangeError::cache(self.file.clone(), cached.clone(), e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    fn revert(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("removes the file '{}' and restores the original", self.file.to_string_lossy())
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
 /// This change creates a directory
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DirectoryChange {
     /// Directory to create
-    directory: PathBuf
+    directory: PathBuf,
+    /// Whether to remove the directory on revert, if it is empty
+    #[serde(default)]
+    owned: bool
 }
 
 impl DirectoryChange {
     pub fn new(directory: PathBuf) -> Self {
-        Self { directory }
+        Self { directory, owned: false }
+    }
+
+    /// Creates a directory which is removed again on revert, if it was created by this change and nothing else was put inside
+    pub fn owned(directory: PathBuf) -> Self {
+        Self { directory, owned: true }
+    }
+
+    /// Path under which is remembered that the directory was created by this change
+    fn cache_key(&self) -> PathBuf {
+        PathBuf::from(format!("{}#directory", self.directory.to_string_lossy()))
     }
 }
 
 #[typetag::serde]
 impl AtomicChange for DirectoryChange {
-    fn apply(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+    fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Remember whether the directory is created, existing directories belong to the user
+        if self.owned && !self.directory.exists() {
+            runtime.cache_store(&self.cache_key(), "")?;
+        }
+
         fs::create_dir_all(&self.directory)
             .map_err(|e| ChangeError::filesystem(self.directory.clone(), "failed to create directory".into(), e.to_string()))?;
 
         Ok(())
     }
 
-    fn revert(&self, _runtime: &ChangeRuntime) -> ChangeResult {
-        Ok(())
+    fn revert(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        if !self.owned || runtime.cache_take(&self.cache_key()).is_none() {
+            return Ok(())
+        }
+
+        // Keep the directory if something else was put inside
+        match fs::remove_dir(&self.directory) {
+            Err(e) if e.kind() != ErrorKind::DirectoryNotEmpty && e.kind() != ErrorKind::NotFound =>
+                Err(ChangeError::filesystem(self.directory.clone(), "failed to remove created directory".into(), e.to_string())),
+            _ => Ok(())
+        }
     }
 
     fn describe(&self) -> String {
@@ -319,13 +431,19 @@ impl WriteChange {
 #[typetag::serde]
 impl AtomicChange for WriteChange {
     fn apply(&self, runtime: &ChangeRuntime) -> Result<(), ChangeError> {
-        // Write the file
-        fs_extra::file::write_all(&self.file, &self.text)
-            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to write to file".into(), e.to_string()))?;
+        // Write the file, unless it already has the content
+        if !same_content(&self.file, self.text.as_bytes()) {
+            fs_extra::file::write_all(&self.file, &self.text)
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to write to file".into(), e.to_string()))?;
+        }
 
-        // Set permissions
-        fs::set_permissions(&self.file, Permissions::from_mode(self.permissions))
-            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to change permissions of created file".into(), e.to_string()))
+        // Set permissions, if they differ
+        if fs::metadata(&self.file).map(|m| m.permissions().mode() & 0o7777 != self.permissions).unwrap_or(true) {
+            fs::set_permissions(&self.file, Permissions::from_mode(self.permissions))
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to change permissions of created file".into(), e.to_string()))?;
+        }
+
+        Ok(())
     }
 
     fn revert(&self, runtime: &ChangeRuntime) -> Result<(), ChangeError> {
@@ -343,6 +461,234 @@ impl AtomicChange for WriteChange {
     }
 }
 
+/// This change manages a marker delimited block inside an existing file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BlockChange {
+    /// File to insert the block into
+    file: PathBuf,
+    /// Line marking the start of the block
+    begin: String,
+    /// Line marking the end of the block
+    end: String,
+    /// Content of the block
+    text: String
+}
+
+impl BlockChange {
+    pub fn new(file: PathBuf, begin: String, end: String, text: String) -> Self {
+        Self { file, begin, end, text }
+    }
+
+    /// Path under which is remembered that the file was created by this change
+    fn cache_key(&self) -> PathBuf {
+        PathBuf::from(format!("{}#block-{}", self.file.to_string_lossy(), self.begin))
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for BlockChange {
+    fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Read current file, which may not exist yet
+        let current = if self.file.exists() {
+            fs::read_to_string(&self.file)
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to read file".into(), e.to_string()))?
+        } else {
+            // Remember that the file is created, so it can be removed again
+            runtime.cache_store(&self.cache_key(), "")?;
+            String::new()
+        };
+
+        // Write file with block
+        fs::write(&self.file, block::insert_block(&current, &self.begin, &self.end, &self.text))
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to write block to file".into(), e.to_string()))
+    }
+
+    fn revert(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        let created = runtime.cache_take(&self.cache_key()).is_some();
+
+        // Nothing to remove if the file is gone
+        if !self.file.exists() {
+            return Ok(())
+        }
+
+        let current = fs::read_to_string(&self.file)
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to read file".into(), e.to_string()))?;
+        let remaining = block::remove_block(&current, &self.begin, &self.end);
+
+        // Remove the file again if it was created for the block and nothing else was written to it
+        if created && remaining.is_empty() {
+            return fs::remove_file(&self.file)
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to remove created file".into(), e.to_string()));
+        }
+
+        // Write file without block
+        fs::write(&self.file, remaining)
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to remove block from file".into(), e.to_string()))
+    }
+
+    fn describe(&self) -> String {
+        format!("writes a block into the file '{}'", self.file.to_string_lossy())
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![("block".to_string(), self.text.clone())]
+    }
+}
+
+/// This change manages a marker delimited block inside a crontab, which is read and written with commands
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CrontabChange {
+    /// Command printing the current crontab
+    read: String,
+    /// Command replacing the crontab with its input
+    write: String,
+    /// Line marking the start of the block
+    begin: String,
+    /// Line marking the end of the block
+    end: String,
+    /// Content of the block
+    text: String,
+
+    /// Running directory
+    dir: PathBuf
+}
+
+impl CrontabChange {
+    pub fn new(read: String, write: String, begin: String, end: String, text: String, dir: PathBuf) -> Self {
+        Self { read, write, begin, end, text, dir }
+    }
+
+    /// Reads the current crontab, which is empty if the user has none yet
+    fn load(&self) -> Result<String, ChangeError> {
+        let result = shell::run_command(&self.read, &self.dir, false)
+            .map_err(|e| ChangeError::command_fatal(self.read.clone(), e))?;
+
+        if result.status.success() {
+            Ok(result.stdout)
+        } else if result.stderr.contains("no crontab for") {
+            // crontab fails if the user has not created a crontab yet
+            Ok(String::new())
+        } else {
+            Err(ChangeError::command(self.read.clone(), result.stdout, result.stderr, result.status.code().unwrap_or(i32::MAX)))
+        }
+    }
+
+    /// Replaces the crontab
+    fn store(&self, crontab: &str) -> ChangeResult {
+        let result = shell::run_command_input(&self.write, &self.dir, crontab, &[], false)
+            .map_err(|e| ChangeError::command_fatal(self.write.clone(), e))?;
+
+        if !result.status.success() {
+            return Err(ChangeError::command(self.write.clone(), result.stdout, result.stderr, result.status.code().unwrap_or(i32::MAX)))
+        }
+
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for CrontabChange {
+    fn apply(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        let current = self.load()?;
+        self.store(&block::insert_block(&current, &self.begin, &self.end, &self.text))
+    }
+
+    fn revert(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        let current = self.load()?;
+        self.store(&block::remove_block(&current, &self.begin, &self.end))
+    }
+
+    fn describe(&self) -> String {
+        "writes a block into the crontab".to_string()
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![("crontab".to_string(), self.text.clone())]
+    }
+}
+
+/// This change sets keys inside a structured config file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PatchChange {
+    /// File to patch
+    file: PathBuf,
+    /// Format of the file
+    format: PatchFormat,
+    /// Keys and values to set
+    fragment: serde_json::Value
+}
+
+impl PatchChange {
+    pub fn new(file: PathBuf, format: PatchFormat, fragment: serde_json::Value) -> Self {
+        Self { file, format, fragment }
+    }
+
+    /// Path under which the previous values are cached, multiple patches of the same file are told apart by their fragment
+    fn cache_key(&self) -> PathBuf {
+        let fragment = chksum::hash::hash::<SHA1, _>(self.fragment.to_string());
+        PathBuf::from(format!("{}#patch-{}", self.file.to_string_lossy(), fragment.to_hex_lowercase()))
+    }
+
+    /// Reads and parses the patched file
+    fn read(&self) -> Result<serde_json::Value, ChangeError> {
+        let text = if self.file.exists() {
+            fs::read_to_string(&self.file)
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to read file".into(), e.to_string()))?
+        } else { String::new() };
+
+        self.format.parse(&text)
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to parse file".into(), e))
+    }
+
+    /// Serializes and writes the patched file
+    fn write(&self, value: &serde_json::Value) -> ChangeResult {
+        let text = self.format.serialize(value)
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to serialize file".into(), e))?;
+
+        fs::write(&self.file, text)
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to write patched file".into(), e.to_string()))
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for PatchChange {
+    fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        let mut value = self.read()?;
+        let previous = patch::apply_patch(&mut value, &self.fragment);
+
+        // Remember previous values before changing anything
+        let data = serde_json::to_string(&previous)
+            .map_err(|e| ChangeError::cache(self.file.clone(), self.cache_key(), e.to_string()))?;
+        runtime.cache_store(&self.cache_key(), &data)?;
+
+        self.write(&value)
+    }
+
+    fn revert(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Only restore if previous values are known
+        let Some(data) = runtime.cache_take(&self.cache_key()) else { return Ok(()) };
+        let previous: Vec<PreviousValue> = serde_json::from_str(&data)
+            .map_err(|e| ChangeError::cache(self.file.clone(), self.cache_key(), e.to_string()))?;
+
+        if !self.file.exists() {
+            return Ok(())
+        }
+
+        let mut value = self.read()?;
+        patch::revert_patch(&mut value, &previous);
+
+        self.write(&value)
+    }
+
+    fn describe(&self) -> String {
+        format!("sets keys inside the file '{}'", self.file.to_string_lossy())
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![("patch".to_string(), self.format.serialize(&self.fragment).unwrap_or_default())]
+    }
+}
+
 /// This change copies a file somewhere
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CopyChange {
@@ -361,8 +707,8 @@ impl CopyChange {
 #[typetag::serde]
 impl AtomicChange for CopyChange {
     fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
-        // Copy files
-        copy(&self.source, &self.file)
+        // Copy files, skipping the ones which are already identical
+        sync(&self.source, &self.file)
             .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to copy file or directory to that location".into(), e.to_string()))?;
 
         Ok(())
@@ -401,8 +747,13 @@ impl LinkChange {
 #[typetag::serde]
 impl AtomicChange for LinkChange {
     fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Skip if the link is already in place
+        if fs::read_link(&self.file).is_ok_and(|target| target == self.source) {
+            return Ok(())
+        }
+
         // Delete old file if it exists
-        if self.file.exists() {
+        if self.file.exists() || self.file.is_symlink() {
             fs::remove_file(&self.file)
                 .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to remove file to create symlink".into(), e.to_string()))?;
         }
@@ -427,6 +778,129 @@ impl AtomicChange for LinkChange {
     }
 }
 
+/// This change extracts an archive into a directory
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExtractChange {
+    /// Archive to extract
+    archive: PathBuf,
+    /// Directory to extract the archive into
+    directory: PathBuf,
+    /// Paths relative to the directory which the archive extracts to
+    entries: Vec<PathBuf>
+}
+
+impl ExtractChange {
+    pub fn new(archive: PathBuf, directory: PathBuf, entries: Vec<PathBuf>) -> Self {
+        Self { archive, directory, entries }
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for ExtractChange {
+    fn apply(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        let format = ArchiveFormat::detect(&self.archive)
+            .ok_or_else(|| ChangeError::filesystem(self.archive.clone(), "failed to detect archive format".into(), "unsupported file extension".into()))?;
+
+        // Extract archive
+        archive::extract(&self.archive, format, &self.directory)
+            .map_err(|e| ChangeError::filesystem(self.directory.clone(), "failed to extract archive there".into(), e.to_string()))
+    }
+
+    fn revert(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        // Delete extracted entries, children come after their parents
+        for entry in self.entries.iter().rev() {
+            let path = self.directory.join(entry);
+
+            match fs::symlink_metadata(&path) {
+                // directories are only removed when nothing else was put inside
+                Ok(meta) if meta.is_dir() => { let _ = fs::remove_dir(&path); }
+                Ok(_) => {
+                    fs::remove_file(&path)
+                        .map_err(|e| ChangeError::filesystem(path.clone(), "failed to remove extracted file".into(), e.to_string()))?;
+                }
+                Err(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("extracts the archive '{}' to '{}'", self.archive.to_string_lossy(), self.directory.to_string_lossy())
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
+/// This change sets the owner, group and mode of a file or directory
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AttributeChange {
+    /// File or directory to change, directories are changed recursively
+    file: PathBuf,
+    /// Name or id of the owning user
+    owner: Option<String>,
+    /// Name or id of the owning group
+    group: Option<String>,
+    /// Permission mode for files, directories are also made traversable where readable
+    permissions: Option<u32>
+}
+
+impl AttributeChange {
+    pub fn new(file: PathBuf, owner: Option<String>, group: Option<String>, permissions: Option<u32>) -> Self {
+        Self { file, owner, group, permissions }
+    }
+
+    /// Path under which the previous attributes are cached, changes of different attributes on the same file are told apart
+    fn cache_key(&self) -> PathBuf {
+        PathBuf::from(format!("{}#attributes-{:?}-{:?}-{:?}", self.file.to_string_lossy(), self.owner, self.group, self.permissions))
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for AttributeChange {
+    fn apply(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Resolve names at apply time, users could have been created by previous jobs
+        let owner = self.owner.as_deref().map(ownership::user_id).transpose()
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to resolve owner".into(), e))?;
+        let group = self.group.as_deref().map(ownership::group_id).transpose()
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to resolve group".into(), e))?;
+
+        // Remember previous attributes before changing anything, the first record is kept like other caches
+        if runtime.cache_load(&self.cache_key()).is_none() {
+            let mut records = vec![];
+            ownership::record(&self.file, &mut records)
+                .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to read ownership and permissions".into(), e.to_string()))?;
+
+            let data = serde_json::to_string(&records)
+                .map_err(|e| ChangeError::cache(self.file.clone(), self.cache_key(), e.to_string()))?;
+            runtime.cache_store(&self.cache_key(), &data)?;
+        }
+
+        ownership::apply(&self.file, owner, group, self.permissions)
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to change ownership or permissions".into(), e.to_string()))
+    }
+
+    fn revert(&self, runtime: &ChangeRuntime) -> ChangeResult {
+        // Only restore if previous attributes are known
+        let Some(data) = runtime.cache_take(&self.cache_key()) else { return Ok(()) };
+        let records: Vec<ownership::Attributes> = serde_json::from_str(&data)
+            .map_err(|e| ChangeError::cache(self.file.clone(), self.cache_key(), e.to_string()))?;
+
+        ownership::restore(&records, self.owner.is_some() || self.group.is_some(), self.permissions.is_some())
+            .map_err(|e| ChangeError::filesystem(self.file.clone(), "failed to restore ownership or permissions".into(), e.to_string()))
+    }
+
+    fn describe(&self) -> String {
+        format!("changes ownership and permissions of '{}'", self.file.to_string_lossy())
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
 /// This change runs a command on the shell
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RunChange {
@@ -495,6 +969,122 @@ impl AtomicChange for RunChange {
     }
 }
 
+/// This change installs packages with a package manager
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PackageChange {
+    /// Package manager used to install and remove the packages
+    manager: ConfigPackage,
+    /// Packages which are declared
+    packages: Vec<String>,
+    /// Declared packages which were present before, so they are neither installed nor removed
+    #[serde(default)]
+    present: Vec<String>,
+
+    /// Running directory
+    dir: PathBuf
+}
+
+impl PackageChange {
+    pub fn new(manager: ConfigPackage, packages: Vec<String>, dir: PathBuf) -> Self {
+        Self { manager, packages, present: vec![], dir }
+    }
+
+    /// Queries which of the packages are installed, with a single invocation of the query command of the package manager.
+    /// A package is installed if a line of the output starts with its name.
+    pub fn query(manager: &ConfigPackage, packages: &[String], dir: &Path) -> Vec<String> {
+        let Some(query) = manager.create_query(packages) else { return vec![] };
+
+        // most query commands fail if one package is missing, so only the output counts
+        let Ok(result) = shell::run_command(&query, dir, false) else { return vec![] };
+
+        result.stdout.lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .filter(|name| packages.iter().any(|p| p == name))
+            .map(|name| name.to_owned())
+            .collect()
+    }
+
+    /// Marks the declared packages which were present before, so they are neither installed nor removed
+    pub fn mark_present(&mut self, present: impl Fn(&str) -> bool) {
+        self.present = self.packages.iter().filter(|p| present(p)).cloned().collect();
+    }
+
+    /// Returns the package manager of the packages
+    pub fn manager(&self) -> &ConfigPackage {
+        &self.manager
+    }
+
+    /// Returns the declared packages
+    pub fn packages(&self) -> &[String] {
+        &self.packages
+    }
+
+    /// Returns the packages which are installed by this change
+    pub fn installed(&self) -> Vec<String> {
+        self.packages.iter().filter(|p| !self.present.contains(p)).cloned().collect()
+    }
+
+    /// Returns the running directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Runs a package manager command
+    fn run(&self, command: String) -> ChangeResult {
+        let result = shell::run_command(&command, &self.dir, true)
+            .map_err(|e| ChangeError::command_fatal(command.clone(), e))?;
+
+        if !result.status.success() {
+            return Err(ChangeError::command(command, result.stdout, result.stderr, result.status.code().unwrap_or(i32::MAX)))
+        }
+
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for PackageChange {
+    fn apply(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        let installed = self.installed();
+        if installed.is_empty() { return Ok(()) }
+
+        self.run(self.manager.create_install(&installed))
+    }
+
+    fn revert(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        let installed = self.installed();
+        if installed.is_empty() { return Ok(()) }
+
+        self.run(self.manager.create_remove(&installed))
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!("installs the package(s) '{}'", self.installed().join("', '"));
+        if !self.present.is_empty() {
+            description += &format!(", keeping the present package(s) '{}'", self.present.join("', '"));
+        }
+
+        description
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        let installed = self.installed();
+
+        vec![
+            ("install".to_string(), self.manager.create_install(&installed)),
+            ("uninstall".to_string(), self.manager.create_remove(&installed))
+        ]
+    }
+
+    fn package(&self) -> Option<&PackageChange> {
+        Some(self)
+    }
+
+    fn package_mut(&mut self) -> Option<&mut PackageChange> {
+        Some(self)
+    }
+}
+
 /// This change runs a command on the shell
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ScriptChange {
@@ -569,6 +1159,132 @@ impl AtomicChange for ScriptChange {
     }
 }
 
+/// This change clones a git repository or updates an existing clone
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GitChange {
+    /// Url of the repository to clone
+    url: String,
+    /// Directory to clone the repository into
+    directory: PathBuf,
+    /// Ref to check out, the default branch if none
+    reference: Option<String>,
+
+    /// Whether to fetch into an existing clone instead of cloning anew
+    update: bool,
+    /// Whether the output of git should be printed
+    interactive: bool
+}
+
+impl GitChange {
+    pub fn new(url: String, directory: PathBuf, reference: Option<String>, update: bool, interactive: bool) -> Self {
+        Self { url, directory, reference, update, interactive }
+    }
+
+    /// Runs git with the given arguments and returns its output
+    fn git(&self, args: &[&str], dir: &Path, interactive: bool) -> Result<String, ChangeError> {
+        let command = format!("git {}", args.join(" "));
+
+        let result = shell::run_program("git", args, dir, interactive)
+            .map_err(|e| ChangeError::command_fatal(command.clone(), e))?;
+
+        if !result.status.success() {
+            return Err(ChangeError::command(command, result.stdout, result.stderr, result.status.code().unwrap_or(i32::MAX)))
+        }
+
+        Ok(result.stdout)
+    }
+
+    /// Checks out the configured ref, or the default branch of the remote
+    fn checkout(&self) -> ChangeResult {
+        let reference = match &self.reference {
+            Some(reference) => reference.clone(),
+            None => {
+                self.git(&["remote", "set-head", "origin", "--auto"], &self.directory, false)?;
+
+                let head = self.git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"], &self.directory, false)?;
+                head.trim().trim_start_matches("origin/").to_owned()
+            }
+        };
+
+        // branches are followed on the remote, everything else is checked out as is
+        let remote = format!("refs/remotes/origin/{reference}");
+        if self.git(&["rev-parse", "--verify", "--quiet", &remote], &self.directory, false).is_ok() {
+            self.git(&["checkout", "-B", &reference, &remote], &self.directory, self.interactive)?;
+        } else {
+            self.git(&["checkout", "--detach", &reference], &self.directory, self.interactive)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl AtomicChange for GitChange {
+    fn apply(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        if self.update && self.directory.join(".git").exists() {
+            // Fetch into existing clone
+            self.git(&["fetch", "--tags", "--force", "origin"], &self.directory, self.interactive)?;
+            self.checkout()
+        } else {
+            // Clone repository freshly
+            let parent = self.directory.parent().unwrap_or(Path::new("/"));
+            let directory = self.directory.to_string_lossy();
+            self.git(&["clone", &self.url, &directory], parent, self.interactive)?;
+
+            if self.reference.is_some() { self.checkout() }
+            else { Ok(()) }
+        }
+    }
+
+    fn revert(&self, _runtime: &ChangeRuntime) -> ChangeResult {
+        // Delete clone
+        fs_extra::remove_items(&[&self.directory])
+            .map_err(|e| ChangeError::filesystem(self.directory.clone(), "failed to remove cloned repository".into(), e.to_string()))
+    }
+
+    fn describe(&self) -> String {
+        if self.update {
+            format!("updates the git repository at '{}' from '{}'", self.directory.to_string_lossy(), self.url)
+        } else {
+            format!("clones the git repository '{}' to '{}'", self.url, self.directory.to_string_lossy())
+        }
+    }
+
+    fn files(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
+/// Checks whether a file already has exactly the given content
+fn same_content(file: &Path, content: &[u8]) -> bool {
+    fs::metadata(file).is_ok_and(|m| m.is_file() && m.len() == content.len() as u64) &&
+        fs::read(file).is_ok_and(|existing| existing == content)
+}
+
+/// Copies either a file or directory, but only writes files whose content differs
+fn sync(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        if !to.is_dir() {
+            if to.exists() || to.is_symlink() { fs::remove_file(to)?; }
+            fs::create_dir_all(to)?;
+        }
+
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            sync(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if fs::read(from).is_ok_and(|content| same_content(to, &content)) {
+            return Ok(())
+        }
+
+        if to.is_dir() { fs::remove_dir_all(to)?; }
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
 /// Copies either a file or directory
 fn copy(from: &Path, to: &Path) -> fs_extra::error::Result<u64>{
     if from.is_dir() {
@@ -578,3 +1294,167 @@ fn copy(from: &Path, to: &Path) -> fs_extra::error::Result<u64>{
         fs_extra::file::copy(from, to, &fs_extra::file::CopyOptions::default().overwrite(true))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::fs::Permissions;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use serde_json::json;
+    use crate::jobs::TestDir;
+    use crate::module::change::{AtomicChange, AttributeChange, BlockChange, ChangeRuntime, ClearChange, CrontabChange, DirectoryChange, PatchChange, WriteChange};
+    use crate::module::change::patch::PatchFormat;
+
+    #[test]
+    fn crontab() {
+        let dir = std::env::temp_dir().join(format!("pusta-crontab-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let runtime = ChangeRuntime { cache: dir.join("cache"), temp: dir.join("temp") };
+        let change = |read: &str| CrontabChange::new(
+            read.into(), "cat > crontab".into(),
+            "# BEGIN test".into(), "# END test".into(),
+            "@daily backup".into(), dir.clone());
+
+        // behaves like crontab for a user without a crontab
+        let read = "cat crontab 2>/dev/null || { echo \"no crontab for $USER\" >&2; exit 1; }";
+
+        change(read).apply(&runtime).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("crontab")).unwrap(), "# BEGIN test\n@daily backup\n# END test\n");
+
+        fs::write(dir.join("crontab"), format!("MAILTO=me\n{}", fs::read_to_string(dir.join("crontab")).unwrap())).unwrap();
+        change(read).revert(&runtime).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("crontab")).unwrap(), "MAILTO=me\n");
+
+        // other failures do not overwrite the crontab
+        assert!(change("echo 'permission denied' >&2; exit 1").apply(&runtime).is_err());
+        assert_eq!(fs::read_to_string(dir.join("crontab")).unwrap(), "MAILTO=me\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn patch_same_file() {
+        let dir = std::env::temp_dir().join(format!("pusta-patch-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let runtime = ChangeRuntime { cache: dir.join("cache"), temp: dir.join("temp") };
+        let file = dir.join("settings.json");
+        fs::write(&file, "{ \"font\": 12, \"theme\": \"light\" }").unwrap();
+
+        let read = || serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&file).unwrap()).unwrap();
+
+        let font = PatchChange::new(file.clone(), PatchFormat::Json, json!({ "font": 14 }));
+        let theme = PatchChange::new(file.clone(), PatchFormat::Json, json!({ "theme": "dark" }));
+
+        font.apply(&runtime).unwrap();
+        theme.apply(&runtime).unwrap();
+        assert_eq!(read(), json!({ "font": 14, "theme": "dark" }));
+
+        // every patch restores only its own keys
+        font.revert(&runtime).unwrap();
+        assert_eq!(read(), json!({ "font": 12, "theme": "dark" }));
+
+        theme.revert(&runtime).unwrap();
+        assert_eq!(read(), json!({ "font": 12, "theme": "light" }));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_keeps_original() {
+        let dir = TestDir::new("cache");
+        let runtime = dir.runtime();
+
+        let file = dir.path.join("config");
+        fs::write(&file, "original").unwrap();
+
+        // two jobs deploy to the same path one after the other
+        let changes: Vec<Box<dyn AtomicChange>> = vec![
+            Box::new(ClearChange::new(file.clone(), false)),
+            Box::new(WriteChange::new("first".into(), 0o644, file.clone())),
+            Box::new(ClearChange::new(file.clone(), false)),
+            Box::new(WriteChange::new("second".into(), 0o644, file.clone()))
+        ];
+
+        for change in &changes { change.apply(&runtime).unwrap(); }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "second");
+
+        for change in changes.iter().rev() { change.revert(&runtime).unwrap(); }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "original");
+    }
+
+    #[test]
+    fn directory_owned() {
+        let dir = TestDir::new("directory");
+        let runtime = dir.runtime();
+
+        // an empty directory of the user is kept
+        let existing = DirectoryChange::owned(dir.path.join("existing"));
+        fs::create_dir_all(dir.path.join("existing")).unwrap();
+        existing.apply(&runtime).unwrap();
+        existing.revert(&runtime).unwrap();
+        assert!(dir.path.join("existing").is_dir());
+
+        // a created directory is removed, unless something else was put inside
+        let created = DirectoryChange::owned(dir.path.join("created"));
+        created.apply(&runtime).unwrap();
+        created.revert(&runtime).unwrap();
+        assert!(!dir.path.join("created").exists());
+
+        created.apply(&runtime).unwrap();
+        fs::write(dir.path.join("created/file"), "user").unwrap();
+        created.revert(&runtime).unwrap();
+        assert!(dir.path.join("created/file").exists());
+    }
+
+    #[test]
+    fn block_created_file() {
+        let dir = TestDir::new("block");
+        let runtime = dir.runtime();
+
+        // a file created for the block is removed again
+        let created = BlockChange::new(dir.path.join("created"), "# begin".into(), "# end".into(), "text".into());
+        created.apply(&runtime).unwrap();
+        created.revert(&runtime).unwrap();
+        assert!(!dir.path.join("created").exists());
+
+        // unless something else was written to it
+        created.apply(&runtime).unwrap();
+        fs::write(dir.path.join("created"), format!("user\n{}", fs::read_to_string(dir.path.join("created")).unwrap())).unwrap();
+        created.revert(&runtime).unwrap();
+        assert_eq!(fs::read_to_string(dir.path.join("created")).unwrap(), "user\n");
+
+        // an existing empty file of the user is kept
+        let existing = BlockChange::new(dir.path.join("existing"), "# begin".into(), "# end".into(), "text".into());
+        fs::write(dir.path.join("existing"), "").unwrap();
+        existing.apply(&runtime).unwrap();
+        existing.revert(&runtime).unwrap();
+        assert!(dir.path.join("existing").exists());
+    }
+
+    #[test]
+    fn attributes_restored() {
+        let dir = TestDir::new("attributes");
+        let runtime = dir.runtime();
+
+        let target = dir.path.join("target");
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join("sub/file"), "").unwrap();
+        fs::set_permissions(target.join("sub/file"), Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(target.join("sub"), Permissions::from_mode(0o700)).unwrap();
+
+        let mode = |path: &str| fs::metadata(target.join(path)).unwrap().permissions().mode() & 0o7777;
+        let gid = fs::metadata(&target).unwrap().gid();
+
+        // the own group can always be set
+        let change = AttributeChange::new(target.clone(), None, Some(gid.to_string()), Some(0o640));
+        change.apply(&runtime).unwrap();
+        assert_eq!((mode("sub"), mode("sub/file")), (0o750, 0o640));
+
+        change.revert(&runtime).unwrap();
+        assert_eq!((mode("sub"), mode("sub/file")), (0o700, 0o600));
+    }
+}
@@ -0,0 +1,147 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// Size the buffer for database entries grows up to
+const MAX_BUFFER: usize = 1 << 20;
+
+/// Looks up an entry in a database of the system with one of the reentrant getpwnam or getgrnam functions, growing the buffer as needed.
+/// This includes users and groups from other sources than the local files, like LDAP.
+///
+/// # Safety
+/// The entry type must be the C struct filled by the function, for which all zero bytes are a valid value.
+unsafe fn lookup<T>(name: &str, function: impl Fn(*const libc::c_char, *mut T, *mut libc::c_char, usize, *mut *mut T) -> libc::c_int) -> Option<T> {
+    let name = CString::new(name).ok()?;
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+
+    loop {
+        // SAFETY: the entry is a plain C struct, and only read if the function filled it
+        let mut entry: T = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+
+        let code = function(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result);
+
+        if code == libc::ERANGE && buffer.len() < MAX_BUFFER {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+
+        return if code == 0 && !result.is_null() { Some(entry) } else { None }
+    }
+}
+
+/// Resolves a user name or id to a user id
+pub fn user_id(name: &str) -> Result<u32, String> {
+    if let Ok(id) = name.parse() { return Ok(id) }
+
+    // SAFETY: the entry is filled by the function, and all pointers are valid for the duration of the call
+    unsafe { lookup(name, |name, entry, buffer, length, result| libc::getpwnam_r(name, entry, buffer, length, result)) }
+        .map(|entry: libc::passwd| entry.pw_uid)
+        .ok_or_else(|| format!("the user '{name}' does not exist"))
+}
+
+/// Resolves a group name or id to a group id
+pub fn group_id(name: &str) -> Result<u32, String> {
+    if let Ok(id) = name.parse() { return Ok(id) }
+
+    // SAFETY: the entry is filled by the function, and all pointers are valid for the duration of the call
+    unsafe { lookup(name, |name, entry, buffer, length, result| libc::getgrnam_r(name, entry, buffer, length, result)) }
+        .map(|entry: libc::group| entry.gr_gid)
+        .ok_or_else(|| format!("the group '{name}' does not exist"))
+}
+
+/// Ownership and mode of a path before it was changed
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Attributes {
+    path: PathBuf,
+    user: u32,
+    group: u32,
+    mode: u32
+}
+
+/// Records the ownership and mode of a path, recursing into directories like apply does
+pub fn record(path: &Path, records: &mut Vec<Attributes>) -> std::io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    records.push(Attributes { path: path.to_owned(), user: meta.uid(), group: meta.gid(), mode: meta.mode() & 0o7777 });
+
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            record(&entry?.path(), records)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Restores recorded ownership and modes, paths which no longer exist are skipped
+pub fn restore(records: &[Attributes], ownership: bool, mode: bool) -> std::io::Result<()> {
+    for record in records {
+        let Ok(meta) = fs::symlink_metadata(&record.path) else { continue };
+
+        if ownership {
+            lchown(&record.path, Some(record.user), Some(record.group))?;
+        }
+
+        // symlinks do not have a mode of their own
+        if mode && !meta.is_symlink() {
+            fs::set_permissions(&record.path, fs::Permissions::from_mode(record.mode))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the mode for a directory, which can be entered wherever it can be read
+pub fn directory_mode(mode: u32) -> u32 {
+    mode | ((mode & 0o444) >> 2)
+}
+
+/// Sets ownership and mode of a path, recursing into directories
+pub fn apply(path: &Path, user: Option<u32>, group: Option<u32>, mode: Option<u32>) -> std::io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+
+    if user.is_some() || group.is_some() {
+        lchown(path, user, group)?;
+    }
+
+    // symlinks do not have a mode of their own
+    if meta.is_symlink() {
+        return Ok(())
+    }
+
+    if meta.is_dir() {
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(directory_mode(mode)))?;
+        }
+
+        for entry in fs::read_dir(path)? {
+            apply(&entry?.path(), user, group, mode)?;
+        }
+    } else if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::module::change::ownership::{directory_mode, group_id, user_id};
+
+    #[test]
+    fn lookup_names() {
+        assert_eq!(user_id("root"), Ok(0));
+        assert_eq!(group_id("root"), Ok(0));
+        assert_eq!(user_id("1000"), Ok(1000));
+        assert!(user_id("pusta-nonexistent-user").is_err());
+    }
+
+    #[test]
+    fn directory_modes() {
+        assert_eq!(directory_mode(0o644), 0o755);
+        assert_eq!(directory_mode(0o640), 0o750);
+        assert_eq!(directory_mode(0o600), 0o700);
+    }
+}
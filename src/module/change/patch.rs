@@ -0,0 +1,209 @@
+use std::path::Path;
+use ini::Ini;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Formats of structured config files which can be patched
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchFormat {
+    Json,
+    Yaml,
+    Toml,
+    Ini
+}
+
+/// Value a key had before it was patched, none if the key did not exist
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PreviousValue {
+    pub key: Vec<String>,
+    pub value: Option<Value>
+}
+
+impl PatchFormat {
+    /// Detects the format of a file by its extension, only for extensions which are unambiguous
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "ini" => Some(Self::Ini),
+            _ => None
+        }
+    }
+
+    /// Parses a document of this format into a tree
+    pub fn parse(&self, text: &str) -> Result<Value, String> {
+        if text.trim().is_empty() {
+            return Ok(Value::Object(Map::new()))
+        }
+
+        match self {
+            PatchFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            PatchFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            PatchFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            PatchFormat::Ini => {
+                let ini = Ini::load_from_str(text).map_err(|e| e.to_string())?;
+
+                // keys without section are stored at the top, sections as objects
+                let mut root = Map::new();
+                for (section, properties) in ini.iter() {
+                    let mut map = Map::new();
+                    for (key, value) in properties.iter() {
+                        map.insert(key.to_owned(), Value::String(value.to_owned()));
+                    }
+
+                    match section {
+                        Some(section) => { root.insert(section.to_owned(), Value::Object(map)); }
+                        None => root.extend(map)
+                    }
+                }
+
+                Ok(Value::Object(root))
+            }
+        }
+    }
+
+    /// Serializes a tree into a document of this format, comments and formatting of a parsed document are not kept
+    pub fn serialize(&self, value: &Value) -> Result<String, String> {
+        match self {
+            PatchFormat::Json => serde_json::to_string_pretty(value).map(|s| s + "\n").map_err(|e| e.to_string()),
+            PatchFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            PatchFormat::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+            PatchFormat::Ini => {
+                let mut ini = Ini::new();
+
+                if let Value::Object(root) = value {
+                    for (key, value) in root {
+                        match value {
+                            Value::Object(section) => {
+                                for (property, value) in section {
+                                    ini.with_section(Some(key.as_str())).set(property.as_str(), ini_value(value));
+                                }
+                            }
+                            value => { ini.with_general_section().set(key.as_str(), ini_value(value)); }
+                        }
+                    }
+                }
+
+                let mut buffer = vec![];
+                ini.write_to(&mut buffer).map_err(|e| e.to_string())?;
+                String::from_utf8(buffer).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Converts a value to the string stored in an ini file
+fn ini_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string()
+    }
+}
+
+/// Sets every leaf of the fragment in the target and returns the previous values of these keys
+pub fn apply_patch(target: &mut Value, fragment: &Value) -> Vec<PreviousValue> {
+    let mut previous = vec![];
+    apply_recursive(target, fragment, &mut vec![], &mut previous);
+    previous
+}
+
+fn apply_recursive(target: &mut Value, fragment: &Value, key: &mut Vec<String>, previous: &mut Vec<PreviousValue>) {
+    let Value::Object(fragment) = fragment else { return };
+
+    // keys can only be set on objects
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else { unreachable!() };
+
+    for (name, value) in fragment {
+        key.push(name.clone());
+
+        match (target.get_mut(name), value) {
+            // descend into existing objects, so only leaves are replaced
+            (Some(existing @ Value::Object(_)), Value::Object(_)) => {
+                apply_recursive(existing, value, key, previous);
+            }
+            (existing, _) => {
+                previous.push(PreviousValue { key: key.clone(), value: existing.cloned() });
+                target.insert(name.clone(), value.clone());
+            }
+        }
+
+        key.pop();
+    }
+}
+
+/// Restores the previous values of patched keys, in reverse order of patching
+pub fn revert_patch(target: &mut Value, previous: &[PreviousValue]) {
+    for item in previous.iter().rev() {
+        let Some((last, parents)) = item.key.split_last() else { continue };
+
+        // find the parent object of the key
+        let pointer: String = parents.iter()
+            .map(|name| format!("/{}", name.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        let Some(Value::Object(parent)) = target.pointer_mut(&pointer) else { continue };
+
+        match &item.value {
+            Some(value) => { parent.insert(last.clone(), value.clone()); }
+            None => { parent.shift_remove(last); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use serde_json::json;
+    use crate::module::change::patch::{apply_patch, PatchFormat, revert_patch};
+
+    #[test]
+    fn detect() {
+        assert_eq!(PatchFormat::detect(Path::new("settings.json")), Some(PatchFormat::Json));
+        assert_eq!(PatchFormat::detect(Path::new("config.TOML")), Some(PatchFormat::Toml));
+        assert_eq!(PatchFormat::detect(Path::new("desktop.ini")), Some(PatchFormat::Ini));
+        assert_eq!(PatchFormat::detect(Path::new("pacman.conf")), None);
+        assert_eq!(PatchFormat::detect(Path::new("setup.cfg")), None);
+    }
+
+    #[test]
+    fn patch_roundtrip() {
+        let original = json!({ "editor": { "fontSize": 12, "theme": "light" }, "other": true });
+        let mut target = original.clone();
+
+        let previous = apply_patch(&mut target, &json!({ "editor": { "fontSize": 14, "ligatures": true }, "new": { "key": 1 } }));
+        assert_eq!(target, json!({ "editor": { "fontSize": 14, "theme": "light", "ligatures": true }, "other": true, "new": { "key": 1 } }));
+
+        revert_patch(&mut target, &previous);
+        assert_eq!(target, original);
+    }
+
+    #[test]
+    fn keys_changed_by_others_stay() {
+        let mut target = json!({ "a": 1, "b": 2 });
+        let previous = apply_patch(&mut target, &json!({ "a": 3 }));
+
+        // the program itself changes another key
+        target["b"] = json!(5);
+
+        revert_patch(&mut target, &previous);
+        assert_eq!(target, json!({ "a": 1, "b": 5 }));
+    }
+
+    #[test]
+    fn ini() {
+        let format = PatchFormat::Ini;
+        let mut target = format.parse("top=1\n[section]\nkey=value\n").unwrap();
+
+        let previous = apply_patch(&mut target, &format.parse("[section]\nkey=other\nadded=yes\n").unwrap());
+        assert_eq!(target["section"]["key"], "other");
+
+        revert_patch(&mut target, &previous);
+        assert_eq!(target, format.parse("top=1\n[section]\nkey=value\n").unwrap());
+        assert!(format.serialize(&target).unwrap().contains("key=value"));
+    }
+}
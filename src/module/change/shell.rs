@@ -1,5 +1,5 @@
 use std::{env, thread};
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Read, Write};
 use std::path::Path;
 use std::process::{Command, ExitStatus, Stdio};
 use std::thread::JoinHandle;
@@ -61,6 +61,44 @@ pub fn run_command(command: &str, dir: &Path, interactive: bool) -> RunResult {
     run(c, interactive)
 }
 
+/// Runs a command on the shell with additional environment variables, passing input on its stdin.
+/// The output is captured unless the command is interactive.
+pub fn run_command_input(command: &str, dir: &Path, input: &str, variables: &[(&str, String)], interactive: bool) -> RunResult {
+    let mut c = Command::new(shell_executable());
+    c.current_dir(dir).arg("-c").arg(command)
+        .envs(variables.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::piped());
+
+    if !interactive {
+        c.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    let mut child = c.spawn().map_err(|_| "could not invoke command".to_string())?;
+
+    // a command which does not read its input may close stdin early, which is fine
+    let _ = child.stdin.take().expect("stdin is always piped").write_all(input.as_bytes());
+
+    let output = child.wait_with_output().map_err(|_| "command did not run when expected to".to_string())?;
+
+    if interactive {
+        Ok(RunData { status: output.status, stdout: "see console".to_string(), stderr: "see console".to_string() })
+    } else {
+        Ok(RunData {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string()
+        })
+    }
+}
+
+/// Runs a program directly with the given arguments, without a shell
+pub fn run_program(program: &str, args: &[&str], dir: &Path, interactive: bool) -> RunResult {
+    let mut c = Command::new(program);
+    c.current_dir(dir).args(args);
+
+    run(c, interactive)
+}
+
 /// Runs a script with the shell
 pub fn run_script(path: &Path, dir: &Path, interactive: bool) -> RunResult {
     let mut c = Command::new(shell_executable());
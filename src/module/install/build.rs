@@ -1,15 +1,17 @@
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 use anyhow::anyhow;
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, TimestampMilliSeconds};
 use serde_with::formats::Flexible;
-use crate::config::ConfigPackage;
+use crate::config::{ConfigPackage, ConfigServices, ConfigTimers};
 use crate::jobs::{BuiltJob, JobEnvironment, JobError};
+use crate::jobs::hooks::BuiltHooks;
 use crate::module::install::InstalledModule;
 use crate::module::Module;
 use crate::module::repository::Repository;
-use crate::variables::{merge_variables, Variable};
+use crate::variables::{load_repositories, merge_variables, Variable};
 
 pub(super) struct ModuleInstructions {
     pub new: Option<BuiltModule>,
@@ -23,6 +25,8 @@ pub(super) struct ModuleInstructions {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct BuiltModule {
     pub jobs: Vec<BuiltJob>,
+    #[serde(default)]
+    pub hooks: BuiltHooks,
     pub used_variables: Variable,
 
     #[serde_as(as = "TimestampMilliSeconds<String, Flexible>")]
@@ -30,9 +34,9 @@ pub struct BuiltModule {
 }
 
 impl BuiltModule {
-    fn new(jobs: Vec<BuiltJob>, used_variables: Variable) -> Self {
+    fn new(jobs: Vec<BuiltJob>, hooks: BuiltHooks, used_variables: Variable) -> Self {
         Self {
-            jobs, used_variables,
+            jobs, hooks, used_variables,
             time: SystemTime::now()
         }
     }
@@ -44,22 +48,28 @@ pub struct ModuleEnvironment {
     pub injected_variables: Variable,
     pub host_variables: Variable,
 
-    pub package_config: ConfigPackage
+    pub package_config: ConfigPackage,
+    pub package_managers: BTreeMap<String, ConfigPackage>,
+    pub service_config: ConfigServices,
+    pub timer_config: ConfigTimers
 }
 
 /// builds a module install
-pub(super) fn install(module: &Module, repository: &Repository, env: &ModuleEnvironment) -> anyhow::Result<ModuleInstructions> {
+pub(super) fn install(module: &Module, repositories: &[&Repository], env: &ModuleEnvironment) -> anyhow::Result<ModuleInstructions> {
     info!("Building module {} for installation", module.qualifier.unique());
 
-    let empty = Variable::base();
-    let variables = merge_variables(module.variables.as_ref().unwrap_or_else(|| &empty),
-                                    repository.load_variables()?.as_ref().unwrap_or_else(|| &empty),
+    let variables = merge_variables(module,
+                                    &load_repositories(repositories)?,
                                     env);
 
     let job_env = JobEnvironment {
         variables: &variables,
         path: module.path.clone(),
-        package_config: env.package_config.clone()
+        resources: module.resource_dirs(),
+        package_config: env.package_config.clone(),
+        package_managers: env.package_managers.clone(),
+        service_config: env.service_config.clone(),
+        timer_config: env.timer_config.clone()
     };
 
     let mut built = vec![];
@@ -69,10 +79,12 @@ pub(super) fn install(module: &Module, repository: &Repository, env: &ModuleEnvi
         built.push(job.build(&job_env).map_err(|e| handle_build_error(&module, e))?);
     }
 
+    let hooks = module.hooks.build(&job_env).map_err(|e| handle_build_error(module, e))?;
+
     Ok(ModuleInstructions {
         apply: vec![true; built.len()],
         revert: vec![],
-        new: Some(BuiltModule::new(built, variables)),
+        new: Some(BuiltModule::new(built, hooks, variables)),
         old: None
     })
 }
@@ -89,19 +101,22 @@ pub(super) fn remove(module: InstalledModule) -> anyhow::Result<ModuleInstructio
 }
 
 /// builds a module update
-pub(super) fn update(installed: InstalledModule, module: &Module, repository: &Repository, env: &ModuleEnvironment) -> anyhow::Result<ModuleInstructions>{
+pub(super) fn update(installed: InstalledModule, module: &Module, repositories: &[&Repository], env: &ModuleEnvironment) -> anyhow::Result<ModuleInstructions>{
     info!("Building update for module {}", module.qualifier.unique());
 
     // build variables and env
-    let empty = Variable::base();
-    let variables = merge_variables(module.variables.as_ref().unwrap_or_else(|| &empty),
-                                    repository.load_variables()?.as_ref().unwrap_or_else(|| &empty),
+    let variables = merge_variables(module,
+                                    &load_repositories(repositories)?,
                                     env);
 
     let job_env = JobEnvironment {
         variables: &variables,
         path: module.path.clone(),
-        package_config: env.package_config.clone()
+        resources: module.resource_dirs(),
+        package_config: env.package_config.clone(),
+        package_managers: env.package_managers.clone(),
+        service_config: env.service_config.clone(),
+        timer_config: env.timer_config.clone()
     };
 
     // create trackers for diff
@@ -109,24 +124,25 @@ pub(super) fn update(installed: InstalledModule, module: &Module, repository: &R
     let mut apply = vec![]; // built to apply
     let mut revert = vec![false; installed.built.jobs.len()]; // old built to revert
 
-    // loop through jobs and check for updates
-    // create a zipped iterator with two options to accommodate for smaller and bigger job arrays
-    let mut new_iter = module.jobs.iter();
-    let mut old_iter = installed.module.jobs.iter().zip(installed.built.jobs.iter()).enumerate();
-
-    for jobs in std::iter::from_fn(|| {
-        let new = new_iter.next();
-        let old = old_iter.next();
+    // match new jobs to old jobs, jobs with an id are matched by it, the others by their position among the jobs without id
+    let mut anonymous = installed.module.jobs.iter().enumerate()
+        .filter(|(_, j)| j.id().is_none())
+        .map(|(index, _)| index);
+    let mut matched = vec![false; installed.built.jobs.len()];
 
-        if new.is_none() && old.is_none() { None }
-        else { Some((new, old))}
-    }) {
+    for new in &module.jobs {
+        let index = match new.id() {
+            Some(id) => installed.module.jobs.iter().position(|j| j.id() == Some(id)),
+            None => anonymous.next()
+        };
 
-        match jobs {
+        match index.map(|index| (index, &installed.module.jobs[index], &installed.built.jobs[index])) {
             // job has changed
-            (Some(new), Some((index, (old, old_built)))) => {
+            Some((index, old, old_built)) => {
+                matched[index] = true;
+
                 if new == old &&
-                    !old_built.resources.iter().any(|i| i.changed(&job_env.path)) &&
+                    !old_built.resources.iter().any(|i| i.changed(&job_env)) &&
                     !old_built.change_variables(&installed.built.used_variables, &variables) {
 
                     // copy job and skip
@@ -135,7 +151,7 @@ pub(super) fn update(installed: InstalledModule, module: &Module, repository: &R
                 } else {
 
                     // partial build is possible
-                    if let Some(result) = new.partial(old, &old_built, &job_env) {
+                    if let Some(result) = new.partial(old, old_built, &job_env) {
                         let result = result.map_err(|e| handle_build_error(&module, e))?;
 
                         // save partial job to be run
@@ -151,21 +167,23 @@ pub(super) fn update(installed: InstalledModule, module: &Module, repository: &R
                 }
             }
             // new job appeared
-            (Some(new), None) => {
+            None => {
                 built.push(new.build(&job_env).map_err(|e| handle_build_error(&module, e))?);
                 apply.push(true);
             }
-            // old job was removed
-            (None, Some((index, _))) => {
-                revert[index] = true;
-            }
-            _ => { unreachable!() }
         }
     }
 
+    // old jobs which were removed
+    for (index, matched) in matched.into_iter().enumerate() {
+        if !matched { revert[index] = true; }
+    }
+
+    let hooks = module.hooks.build(&job_env).map_err(|e| handle_build_error(module, e))?;
+
     Ok(ModuleInstructions {
         apply, revert,
-        new: Some(BuiltModule::new(built, variables)),
+        new: Some(BuiltModule::new(built, hooks, variables)),
         old: Some(installed.built)
     })
 }
@@ -41,10 +41,18 @@ pub struct Resolver {
 
     /// what module is what
     action: HashMap<ModuleQualifier, ResolvingAction>,
+
+    /// modules which do not apply to this system and thus cannot be resolved
+    inapplicable: Vec<ModuleQualifier>
 }
 
 impl Resolver {
 
+    /// creates a resolver which refuses modules that do not apply to this system
+    pub fn new(inapplicable: Vec<ModuleQualifier>) -> Self {
+        Self { inapplicable, ..Self::default() }
+    }
+
     /// mark a module as installed
     pub fn install(&mut self, module: &ModuleQualifier, local: &Index<InstalledModule>, available: &Index<Module>) -> anyhow::Result<()> {
         let module = available.get(&module).context("module disappeared unexpectedly")?;
@@ -85,6 +93,11 @@ impl Resolver {
     /// resolve a module and its dependencies
     fn resolve(&mut self, module: &Module, action: ResolvingAction, local: &Index<InstalledModule>, available: &Index<Module>) -> anyhow::Result<()> {
 
+        if self.inapplicable.contains(&module.qualifier) {
+            error!("module {} does not apply to this system", module.qualifier.unique());
+            return Err(anyhow!("could not resolve dependencies"));
+        }
+
         let mut dependencies = vec![];
 
         // iterate over dependencies of module
@@ -106,8 +119,10 @@ impl Resolver {
                 continue
             }
 
-            // search through installable
-            let providers = available.providers(&dep);
+            // search through installable, which apply to this system
+            let providers = available.providers(&dep).into_iter()
+                .filter(|m| !self.inapplicable.contains(&m.qualifier))
+                .collect::<Vec<_>>();
             if let Some(m) = prompt_choice_module(
                 &providers,
                 &format!("Multiple modules provide dependency '{dep}' for {}, choose:", module.qualifier.unique())).and_then(|i| providers.get(i).copied()) {
@@ -246,7 +261,8 @@ mod tests {
         let resolver = Resolver {
             dependency: map,
             action: HashMap::new(),
-            removals: vec![]
+            removals: vec![],
+            inapplicable: vec![]
         };
 
         let order = resolver.get_order().into_iter().map(|q| q.name().clone()).collect::<Vec<String>>();
@@ -280,7 +296,8 @@ mod tests {
         let resolver = Resolver {
             dependency: map,
             action: installed,
-            removals: vec![]
+            removals: vec![],
+            inapplicable: vec![]
         };
 
         let order = resolver.get_order().into_iter().map(|q| q.name().clone()).collect::<Vec<String>>();
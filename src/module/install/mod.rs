@@ -2,15 +2,17 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Write};
 use std::fs;
 use std::path::Path;
+use std::env;
 use std::process::Command;
 use std::str::FromStr;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use colored::{ColoredString, Colorize};
-use log::{error, info};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use crate::config::Config;
 use crate::jobs::BuiltJob;
 use crate::module::install::build::{BuiltModule, ModuleEnvironment, ModuleInstructions};
+use crate::module::change::shell;
 use crate::module::install::depend::{ModuleMotivation, Resolver, ResolvingAction};
 use crate::module::Module;
 use crate::module::qualifier::ModuleQualifier;
@@ -18,12 +20,13 @@ use crate::output::logger::section;
 use crate::output::prompt;
 use crate::registry::cache::Cache;
 use crate::registry::index::{Index, Indexable};
-use crate::variables::{construct_host, construct_injected, generate_magic, load_system, merge_variables, Variable};
+use crate::variables::{construct_host, construct_injected, generate_magic, load_repositories, load_system, merge_variables, Variable};
 
 use super::host::Host;
 
 pub mod build;
 pub mod depend;
+mod plan;
 mod run;
 
 /// This struct helps gathering module changes
@@ -34,6 +37,11 @@ pub struct Gatherer {
 
 impl Gatherer {
 
+    /// Creates a gatherer which refuses modules that do not apply to this system
+    pub fn new(inapplicable: Vec<ModuleQualifier>) -> Self {
+        Self { resolver: Resolver::new(inapplicable) }
+    }
+
     pub fn install(&mut self, module: ModuleQualifier, local: &Index<InstalledModule>, index: &Index<Module>) -> anyhow::Result<()>{
         self.resolver.install(&module, local, index)
     }
@@ -64,18 +72,18 @@ impl Gatherer {
                     let module = index.get(&q).context("module disappeared unexpectedly")?;
                     let installed_module = local.get(&q).context("module disappeared unexpectedly")?;
 
-                    modules.push(Scheduled::Remove { module: installed_module.clone() });
+                    modules.push(Scheduled::Remove { module: Box::new(installed_module.clone()) });
                     modules.push(Scheduled::Install { module: module.clone(), motivation: r });
                 }
                 ResolvingAction::Update => {
                     let module = index.get(&q).context("module disappeared unexpectedly")?;
                     let installed_module = local.get(&q).context("module disappeared unexpectedly")?;
 
-                    modules.push(Scheduled::Update { new: module.clone(), old: installed_module.clone() });
+                    modules.push(Scheduled::Update { new: module.clone(), old: Box::new(installed_module.clone()) });
                 }
                 ResolvingAction::Remove => {
                     let installed_module = local.get(&q).context("module disappeared unexpectedly")?;
-                    modules.push(Scheduled::Remove { module: installed_module.clone() })
+                    modules.push(Scheduled::Remove { module: Box::new(installed_module.clone()) })
                 }
                 _ => { unreachable!("no placeholders here") }
             }
@@ -91,10 +99,10 @@ enum Scheduled {
         motivation: ModuleMotivation
     },
     Remove {
-        module: InstalledModule,
+        module: Box<InstalledModule>,
     },
     Update {
-        old: InstalledModule,
+        old: Box<InstalledModule>,
         new: Module,
     }
 }
@@ -109,14 +117,13 @@ pub struct InstalledModule {
 impl InstalledModule {
     pub fn up_to_date(&self, new: &Module, env: &ModuleEnvironment, cache: &Cache) -> bool {
         if let Some(repo) = cache.get_repository(self.module.qualifier.repository()) {
-            let empty = Variable::base();
-            let variables = merge_variables(new.variables.as_ref().unwrap_or_else(|| &empty),
-                                            repo.load_variables().unwrap_or(None).as_ref().unwrap_or_else(|| &empty),
+            let variables = merge_variables(new,
+                                            &load_repositories(&cache.get_repository_chain(repo)).unwrap_or_default(),
                                             env);
 
             // either the module sources have changed
             new.checksum == self.module.checksum &&
-                !self.built.jobs.iter().any(|j| j.change_variables(&self.built.used_variables, &variables))
+                !self.built.jobs.iter().chain(self.built.hooks.jobs()).any(|j| j.change_variables(&self.built.used_variables, &variables))
         } else {
             // assume up to date if orphaned
             true
@@ -143,6 +150,14 @@ impl ModifyType {
         }
     }
 
+    /// Short name of the action, as exposed to hooks
+    pub fn action(&self) -> &'static str {
+        match self {
+            ModifyType::Install => { "install" }
+            ModifyType::Remove => { "remove" }
+            ModifyType::Update => { "update" }
+        }
+    }
 }
 impl Display for ModifyType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -175,6 +190,9 @@ fn build(scheduled: Vec<Scheduled>, cache: &Cache, hosts: &Vec<Host>, config: &C
 
     let env = ModuleEnvironment {
         package_config: config.system.package_manager.clone(),
+        package_managers: config.system.package_managers.clone(),
+        service_config: config.system.service_manager.clone(),
+        timer_config: config.system.timers.clone(),
         magic_variables: generate_magic(),
         system_variables: load_system(config).unwrap_or_else(|| Variable::base()),
         host_variables: construct_host(hosts),
@@ -185,17 +203,17 @@ fn build(scheduled: Vec<Scheduled>, cache: &Cache, hosts: &Vec<Host>, config: &C
         built.push(match scheduled {
             Scheduled::Install { module, motivation } => {
                 let repository = cache.get_repository(module.qualifier.repository()).expect("module from no repository");
-                let built = build::install(&module, repository, &env)?;
+                let built = build::install(&module, &cache.get_repository_chain(repository), &env)?;
                 (module, built, motivation, ModifyType::Install)
             }
             Scheduled::Remove { module } => {
                 let core = module.module.clone();
-                let built = build::remove(module)?;
+                let built = build::remove(*module)?;
                 (core, built, ModuleMotivation::default(), ModifyType::Remove)
             }
             Scheduled::Update { old, new } => {
                 let repository = cache.get_repository(new.qualifier.repository()).expect("module from no repository");
-                let built = build::update(old, &new, repository, &env)?;
+                let built = build::update(*old, &new, &cache.get_repository_chain(repository), &env)?;
                 (new, built, ModuleMotivation::default(), ModifyType::Update)
             }
         });
@@ -337,7 +355,7 @@ pub fn modify(gatherer: Gatherer, index: &Index<Module>, cache: &mut Cache, host
 
     // 2. build
     section("Building modules...");
-    let built = match build(scheduled, &cache, hosts, config) {
+    let mut built = match build(scheduled, &cache, hosts, config) {
         Ok(b) => { b }
         Err(e) => {
             error!("{e}");
@@ -347,6 +365,8 @@ pub fn modify(gatherer: Gatherer, index: &Index<Module>, cache: &mut Cache, host
 
     // 3. ask
     section("Preparing modifications...");
+    plan::query(built.iter_mut().map(|(_, i, _, _)| i), &cache.index.modules);
+
     println!();
     if !ask(&built, &config.system.file_previewer) {
         error!("installation cancelled by user");
@@ -354,6 +374,14 @@ pub fn modify(gatherer: Gatherer, index: &Index<Module>, cache: &mut Cache, host
     }
     println!();
 
+    if let Some(hook) = &config.hooks.before_modify {
+        section("Running hook before modifications...");
+        if let Err(e) = run_config_hook(hook, "before_modify", &built, None) {
+            error!("hook before modifications failed, cancelling: {e}");
+            return;
+        }
+    }
+
     // 4. run
     section("Applying modifications...");
     let result = match run::run(&built.iter().map(|(m, i, mo, _)| (i, m, mo)).collect(), &config, &cache) {
@@ -364,6 +392,13 @@ pub fn modify(gatherer: Gatherer, index: &Index<Module>, cache: &mut Cache, host
         }
     };
 
+    if let Some(hook) = &config.hooks.after_modify {
+        section("Running hook after modifications...");
+        if let Err(e) = run_config_hook(hook, "after_modify", &built, Some(&result)) {
+            warn!("hook after modifications failed: {e}");
+        }
+    }
+
     // 5. save
     section("Persisting changes...");
     if let Err(e) = save(built, result, cache) {
@@ -373,6 +408,33 @@ pub fn modify(gatherer: Gatherer, index: &Index<Module>, cache: &mut Cache, host
     }
 }
 
+/// Runs a hook from the config, passing the modified modules as json on stdin and as an environment variable
+fn run_config_hook(command: &str, name: &str, changes: &[(Module, ModuleInstructions, ModuleMotivation, ModifyType)], results: Option<&[Option<bool>]>) -> anyhow::Result<()> {
+    let modules = changes.iter().enumerate().map(|(index, (module, _, _, what))| {
+        serde_json::json!({
+            "qualifier": module.qualifier.unique(),
+            "name": module.name,
+            "action": what.action(),
+            "success": results.and_then(|r| r.get(index).copied().flatten())
+        })
+    }).collect::<Vec<_>>();
+
+    let variables = [
+        ("PUSTA_HOOK", name.to_owned()),
+        ("PUSTA_MODULES", changes.iter().map(|(m, _, _, _)| m.qualifier.unique()).collect::<Vec<_>>().join(" "))
+    ];
+
+    let dir = env::current_dir().context("could not determine the working directory")?;
+    let result = shell::run_command_input(command, &dir, &serde_json::to_string(&modules)?, &variables, true)
+        .map_err(|e| anyhow!("hook {e}"))?;
+
+    if !result.status.success() {
+        return Err(anyhow!("hook command exited with status {}", result.status.code().unwrap_or(i32::MAX)));
+    }
+
+    Ok(())
+}
+
 
 const TMP_PREVIEW_PATH: &str = "/tmp/pusta/preview";
 
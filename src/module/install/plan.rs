@@ -0,0 +1,355 @@
+use std::path::{Path, PathBuf};
+use crate::config::ConfigPackage;
+use crate::jobs::BuiltJob;
+use crate::module::change::{AtomicChange, PackageChange};
+use crate::module::install::build::{BuiltModule, ModuleInstructions};
+use crate::module::install::InstalledModule;
+
+/// Packages of one package manager, which are installed or removed in a single invocation
+pub struct Batch {
+    pub manager: ConfigPackage,
+    pub dir: PathBuf,
+    /// packages of every module taking part
+    pub owners: Vec<Owner>
+}
+
+/// Packages a module takes part in a batch with
+pub struct Owner {
+    /// index of the module in the instructions
+    pub module: usize,
+    pub packages: Vec<String>,
+    /// whether the packages were processed successfully
+    pub done: bool
+}
+
+impl Batch {
+    /// Adds packages of a module to the batch of their package manager
+    fn add(batches: &mut Vec<Batch>, module: usize, manager: &ConfigPackage, dir: &Path, packages: Vec<String>) {
+        if packages.is_empty() { return }
+
+        let batch = match batches.iter().position(|b| &b.manager == manager) {
+            Some(index) => &mut batches[index],
+            None => {
+                batches.push(Batch { manager: manager.clone(), dir: dir.to_owned(), owners: vec![] });
+                batches.last_mut().expect("batch was just added")
+            }
+        };
+
+        let owner = match batch.owners.iter().position(|o| o.module == module) {
+            Some(index) => &mut batch.owners[index],
+            None => {
+                batch.owners.push(Owner { module, packages: vec![], done: false });
+                batch.owners.last_mut().expect("owner was just added")
+            }
+        };
+
+        for package in packages {
+            if !owner.packages.contains(&package) { owner.packages.push(package) }
+        }
+    }
+
+    /// Returns the packages of all modules in the batch, without duplicates
+    pub fn packages(&self) -> Vec<String> {
+        let mut packages: Vec<String> = vec![];
+
+        for package in self.owners.iter().flat_map(|o| &o.packages) {
+            if !packages.contains(package) { packages.push(package.clone()) }
+        }
+
+        packages
+    }
+
+    /// Creates the change which processes the given packages of the batch
+    pub fn change(&self, packages: Vec<String>) -> Box<dyn AtomicChange> {
+        Box::new(PackageChange::new(self.manager.clone(), packages, self.dir.clone()))
+    }
+}
+
+/// Returns the package changes of the jobs of a module which are selected
+fn selected<'a>(module: &'a Option<BuiltModule>, selection: &'a [bool]) -> impl Iterator<Item = &'a PackageChange> {
+    module.iter()
+        .flat_map(move |m| m.jobs.iter().zip(selection))
+        .filter(|(_, selected)| **selected)
+        .flat_map(|(job, _)| job.changes.iter())
+        .filter_map(|c| c.package())
+}
+
+/// Returns whether one of the package changes contains a package of a package manager
+fn contains(changes: &[&PackageChange], manager: &ConfigPackage, package: &str) -> bool {
+    changes.iter().any(|c| c.manager() == manager && c.packages().iter().any(|p| p == package))
+}
+
+/// Returns the package changes of all jobs of modules
+pub fn declared<'a>(modules: impl Iterator<Item = &'a BuiltModule>) -> impl Iterator<Item = &'a PackageChange> {
+    modules.flat_map(|m| &m.jobs)
+        .flat_map(|job| job.changes.iter())
+        .filter_map(|c| c.package())
+}
+
+/// Queries which packages of the applied jobs are present on the system already, packages installed by pusta for installed modules are not considered present.
+/// Every package manager is queried once for all packages.
+pub fn query<'a>(instructions: impl Iterator<Item = &'a mut ModuleInstructions>, installed: &[InstalledModule]) {
+    let managed: Vec<&PackageChange> = declared(installed.iter().map(|m| &m.built)).collect();
+    let mut instructions: Vec<&mut ModuleInstructions> = instructions.collect();
+
+    // collect the candidates of every package manager
+    let mut batches = vec![];
+    for (index, instruction) in instructions.iter().enumerate() {
+        for change in selected(&instruction.new, &instruction.apply) {
+            let packages = change.packages().iter()
+                .filter(|p| !contains(&managed, change.manager(), p))
+                .cloned().collect();
+
+            Batch::add(&mut batches, index, change.manager(), change.dir(), packages);
+        }
+    }
+
+    let present: Vec<(&ConfigPackage, Vec<String>)> = batches.iter()
+        .map(|batch| (&batch.manager, PackageChange::query(&batch.manager, &batch.packages(), &batch.dir)))
+        .collect();
+
+    for instruction in &mut instructions {
+        let Some(module) = &mut instruction.new else { continue };
+
+        for (job, _) in module.jobs.iter_mut().zip(&instruction.apply).filter(|(_, apply)| **apply) {
+            for change in job.changes.iter_mut().filter_map(|c| c.package_mut()) {
+                let manager = change.manager().clone();
+                change.mark_present(|package| !contains(&managed, &manager, package) &&
+                    present.iter().any(|(m, packages)| **m == manager && packages.iter().any(|p| p == package)));
+            }
+        }
+    }
+}
+
+/// Returns whether a job changes more than packages, which are processed in batches
+fn effective(job: &BuiltJob) -> bool {
+    job.changes.iter().any(|c| c.package().is_none())
+}
+
+/// Selects the applied jobs of every module whose packages can be installed in batches before everything else.
+/// This is not the case if anything has to run before them, which is a hook or reversal of an earlier or the same module, the pre-install hook of the module, or jobs it is declared after.
+/// The packages of other jobs are installed in order, together with the rest of their job.
+pub fn batched(instructions: &[&ModuleInstructions]) -> Vec<Vec<bool>> {
+    let mut blocked = false;
+
+    instructions.iter().map(|instruction| {
+        let removal = instruction.new.is_none() && instruction.old.as_ref().is_some_and(|m| m.hooks.pre_remove.is_some());
+        let reversal = instruction.old.iter()
+            .flat_map(|m| m.jobs.iter().zip(&instruction.revert))
+            .any(|(job, revert)| *revert && effective(job));
+
+        blocked |= removal || reversal;
+
+        let hook = instruction.old.is_none() && instruction.new.as_ref().is_some_and(|m| m.hooks.pre_install.is_some());
+
+        instruction.new.iter()
+            .flat_map(|m| m.jobs.iter().zip(&instruction.apply))
+            .map(|(job, apply)| *apply && !blocked && !hook && !job.ordered)
+            .collect()
+    }).collect()
+}
+
+/// Creates the change which installs the packages of a job on its own, packages declared by installed modules are already present
+pub fn inline(change: &PackageChange, present: &[&PackageChange]) -> Box<dyn AtomicChange> {
+    let packages = change.installed().into_iter()
+        .filter(|p| !contains(present, change.manager(), p))
+        .collect();
+
+    Box::new(PackageChange::new(change.manager().clone(), packages, change.dir().to_owned()))
+}
+
+/// Records the packages a module installed on its own, so they are removed again like batched packages if the module fails
+pub fn record(batches: &mut Vec<Batch>, module: usize, change: &PackageChange) {
+    Batch::add(batches, module, change.manager(), change.dir(), change.installed());
+
+    batches.iter_mut().filter(|b| b.manager == *change.manager())
+        .flat_map(|b| b.owners.iter_mut())
+        .filter(|o| o.module == module)
+        .for_each(|o| o.done = true);
+}
+
+/// Coalesces the packages installed by the selected jobs of all modules into one batch per package manager, packages declared by installed modules are already present
+pub fn installs(instructions: &[&ModuleInstructions], batched: &[Vec<bool>], installed: &[&BuiltModule]) -> Vec<Batch> {
+    let present: Vec<&PackageChange> = declared(installed.iter().copied()).collect();
+
+    let mut batches = vec![];
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        for change in selected(&instruction.new, &batched[index]) {
+            let packages = change.installed().into_iter()
+                .filter(|p| !contains(&present, change.manager(), p))
+                .collect();
+
+            Batch::add(&mut batches, index, change.manager(), change.dir(), packages);
+        }
+    }
+
+    batches
+}
+
+/// Coalesces the packages which are no longer needed into one batch per package manager, these are the packages installed for reverted jobs and for modules which failed
+pub fn removals(instructions: &[&ModuleInstructions], others: &[&BuiltModule], installs: &[Batch], reverted: &[bool], results: &[Option<bool>]) -> Vec<Batch> {
+    // packages still declared by modules which are installed are kept
+    let wanted: Vec<&PackageChange> = declared(instructions.iter().zip(results)
+        .filter(|(_, result)| **result == Some(true))
+        .flat_map(|(i, _)| &i.new)
+        .chain(others.iter().copied()))
+        .collect();
+
+    let mut batches = vec![];
+
+    for (index, instruction) in instructions.iter().enumerate().filter(|(index, _)| reverted[*index]) {
+        for change in selected(&instruction.old, &instruction.revert) {
+            let packages = change.installed().into_iter()
+                .filter(|p| !contains(&wanted, change.manager(), p))
+                .collect();
+
+            Batch::add(&mut batches, index, change.manager(), change.dir(), packages);
+        }
+    }
+
+    for batch in installs {
+        for owner in batch.owners.iter().filter(|o| o.done && results[o.module] != Some(true)) {
+            let packages = owner.packages.iter()
+                .filter(|p| !contains(&wanted, &batch.manager, p))
+                .cloned().collect();
+
+            Batch::add(&mut batches, owner.module, &batch.manager, &batch.dir, packages);
+        }
+    }
+
+    batches
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+    use crate::config::ConfigPackage;
+    use crate::jobs::BuiltJob;
+    use crate::jobs::hooks::BuiltHooks;
+    use crate::module::change::{AtomicChange, PackageChange, RunChange};
+    use crate::module::install::build::{BuiltModule, ModuleInstructions};
+    use crate::jobs::TestDir;
+    use crate::module::install::plan::{batched, inline, installs, query, record, removals};
+    use crate::variables::Variable;
+
+    fn manager(name: &str) -> ConfigPackage {
+        ConfigPackage { name: Some(name.into()), ..Default::default() }
+    }
+
+    fn module(packages: &[(&str, &[&str])]) -> Option<BuiltModule> {
+        let jobs = packages.iter().map(|(name, packages)| {
+            let mut job = BuiltJob::new();
+            job.change(Box::new(PackageChange::new(manager(name), packages.iter().map(|s| s.to_string()).collect(), PathBuf::new())));
+            job
+        }).collect();
+
+        Some(BuiltModule { jobs, hooks: BuiltHooks::default(), used_variables: Variable::base(), time: SystemTime::now() })
+    }
+
+    fn install(packages: &[(&str, &[&str])]) -> ModuleInstructions {
+        ModuleInstructions { apply: vec![true; packages.len()], revert: vec![], new: module(packages), old: None }
+    }
+
+    #[test]
+    fn coalesce() {
+        let a = install(&[("pacman", &["git", "vim"]), ("flatpak", &["spotify"])]);
+        let b = install(&[("pacman", &["git", "zsh"])]);
+
+        let batches = installs(&[&a, &b], &batched(&[&a, &b]), &[]);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].packages(), vec!["git", "vim", "zsh"]);
+        assert_eq!(batches[0].owners.len(), 2);
+        assert_eq!(batches[1].packages(), vec!["spotify"]);
+    }
+
+    #[test]
+    fn keep_wanted() {
+        // an update which reverts the old package job and applies a new one
+        let old = module(&[("pacman", &["git", "bash"])]).unwrap();
+        let update = ModuleInstructions {
+            apply: vec![true], revert: vec![true],
+            new: module(&[("pacman", &["git", "zsh"])]),
+            old: Some(old.clone())
+        };
+
+        let mut batches = installs(&[&update], &[vec![true]], &[&old]);
+        assert_eq!(batches[0].packages(), vec!["zsh"]);
+        batches[0].owners[0].done = true;
+
+        // only the package which is no longer declared is removed
+        let removed = removals(&[&update], &[], &batches, &[true], &[Some(true)]);
+        assert_eq!(removed[0].packages(), vec!["bash"]);
+
+        // everything is removed if the update failed
+        let removed = removals(&[&update], &[], &batches, &[true], &[Some(false)]);
+        assert_eq!(removed[0].packages(), vec!["git", "bash", "zsh"]);
+
+        // but not if another installed module declares it
+        let other = module(&[("pacman", &["bash"])]).unwrap();
+        let removed = removals(&[&update], &[&other], &batches, &[true], &[Some(false)]);
+        assert_eq!(removed[0].packages(), vec!["git", "zsh"]);
+    }
+
+    #[test]
+    fn batch_unless_preceded() {
+        let plain = install(&[("pacman", &["git"])]);
+
+        // the pre-install hook has to run before the packages
+        let mut hooked = install(&[("pacman", &["zsh"])]);
+        hooked.new.as_mut().unwrap().hooks.pre_install = Some(BuiltJob::new());
+
+        // the second job is declared after the first one
+        let mut ordered = install(&[("pacman", &["vim"]), ("pacman", &["neovim"])]);
+        ordered.new.as_mut().unwrap().jobs[1].ordered = true;
+
+        let selection = batched(&[&plain, &hooked, &ordered]);
+        assert_eq!(selection, vec![vec![true], vec![false], vec![true, false]]);
+
+        let mut batches = installs(&[&plain, &hooked, &ordered], &selection, &[]);
+        assert_eq!(batches[0].packages(), vec!["git", "vim"]);
+
+        // packages installed on their own are removed like batched ones if their module fails
+        batches[0].owners.iter_mut().for_each(|o| o.done = true);
+        let change = hooked.new.as_ref().unwrap().jobs[0].changes[0].package().unwrap();
+        record(&mut batches, 1, change);
+        assert_eq!(inline(change, &[]).package().unwrap().installed(), vec!["zsh"]);
+        assert!(inline(change, &[change]).package().unwrap().installed().is_empty());
+
+        let removed = removals(&[&plain, &hooked, &ordered], &[], &batches, &[false; 3], &[Some(true), Some(false), Some(true)]);
+        assert_eq!(removed[0].packages(), vec!["zsh"]);
+
+        // nothing is batched after a module reverted its jobs
+        let mut reverting = ModuleInstructions { apply: vec![], revert: vec![true], new: module(&[]), old: module(&[]) };
+        let mut file = BuiltJob::new();
+        file.change(Box::new(RunChange::new("true".into(), None, PathBuf::new(), false, false)));
+        reverting.old.as_mut().unwrap().jobs.push(file);
+
+        assert_eq!(batched(&[&plain, &reverting, &plain]), vec![vec![true], vec![], vec![false]]);
+    }
+
+    #[test]
+    fn query_once() {
+        let dir = TestDir::new("query");
+
+        // prints the installed packages and counts its invocations
+        let pacman = ConfigPackage { query: Some("echo %PACKAGE% >> calls; printf 'git 2.0\\nvim 9.0\\n'".into()), ..manager("pacman") };
+        let change = |packages: &[&str]| {
+            let mut job = BuiltJob::new();
+            job.change(Box::new(PackageChange::new(pacman.clone(), packages.iter().map(|s| s.to_string()).collect(), dir.path.clone())));
+            job
+        };
+
+        let module = |jobs| Some(BuiltModule { jobs, hooks: BuiltHooks::default(), used_variables: Variable::base(), time: SystemTime::now() });
+        let mut a = ModuleInstructions { apply: vec![true], revert: vec![], new: module(vec![change(&["git", "zsh"])]), old: None };
+        let mut b = ModuleInstructions { apply: vec![true, true], revert: vec![], new: module(vec![change(&["vim"]), change(&["git", "fish"])]), old: None };
+
+        query([&mut a, &mut b].into_iter(), &[]);
+        assert_eq!(std::fs::read_to_string(dir.path.join("calls")).unwrap(), "git zsh vim fish\n");
+
+        // only the packages which are not present are installed
+        let batches = installs(&[&a, &b], &batched(&[&a, &b]), &[]);
+        assert_eq!(batches[0].packages(), vec!["zsh", "fish"]);
+    }
+}
@@ -2,10 +2,12 @@ use std::path::Path;
 use log::{debug, error, info, warn};
 use crate::config::Config;
 use crate::jobs::BuiltJob;
-use crate::module::install::build::ModuleInstructions;
+use crate::module::install::build::{BuiltModule, ModuleInstructions};
 use crate::module::install::depend::ModuleMotivation;
+use crate::module::install::plan;
+use crate::module::install::plan::Batch;
 use crate::module::Module;
-use crate::module::change::{AtomicChange, ChangeError};
+use crate::module::change::{AtomicChange, ChangeError, PackageChange};
 use crate::module::change::worker::WorkerPortal;
 use crate::registry::cache::Cache;
 
@@ -21,10 +23,12 @@ pub(super) fn run(instructions: &Vec<(&ModuleInstructions, &Module, &ModuleMotiv
     if instructions.iter()
         .any(|(i, _, _)| {
             let removal = if let Some(new) = &i.new {
-                new.jobs.iter().zip(&i.apply).any(|(j, b)| *b && j.root)
+                new.jobs.iter().zip(&i.apply).any(|(j, b)| (*b || !j.refresh.is_empty()) && j.root) ||
+                    new.hooks.jobs().any(|j| j.root)
             } else { false };
             let apply = if let Some(old) = &i.old {
-                old.jobs.iter().zip(&i.revert).any(|(j, b)| *b && j.root)
+                old.jobs.iter().zip(&i.revert).any(|(j, b)| *b && j.root) ||
+                    old.hooks.jobs().any(|j| j.root)
             } else { false };
 
             removal || apply
@@ -36,10 +40,40 @@ pub(super) fn run(instructions: &Vec<(&ModuleInstructions, &Module, &ModuleMotiv
 
     let mut results = vec![None; instructions.len()];
     let mut failed = vec![];
+    let mut reverted = vec![false; instructions.len()];
+
+    // packages of all modules are installed in batches before anything else, as long as nothing has to run before them
+    let modules: Vec<&ModuleInstructions> = instructions.iter().map(|(i, _, _)| *i).collect();
+    let installed: Vec<&BuiltModule> = cache.index.modules.iter().map(|m| &m.built).collect();
+    let batched = plan::batched(&modules);
+    let mut installs = plan::installs(&modules, &batched, &installed);
+    let present: Vec<&PackageChange> = plan::declared(installed.iter().copied()).collect();
+
+    if !installs.is_empty() {
+        info!("Installing packages...");
+    }
+
+    for batch in &mut installs {
+        run_batch(batch, instructions, cache, &mut workers, true)?;
+
+        for owner in batch.owners.iter().filter(|o| !o.done) {
+            let (_, source, _) = instructions[owner.module];
+            error!("Failed to install the packages of module {}", source.qualifier.unique());
+
+            results[owner.module] = Some(false);
+            failed.push(source.qualifier.clone());
+        }
+    }
 
     info!("Applying changes...");
     'install: for (index, (instruction, source, motivation)) in instructions.iter().enumerate() {
         info!("Processing module {}", source.qualifier.unique());
+
+        if results[index] == Some(false) {
+            info!("Skipping because its packages could not be installed");
+            continue;
+        }
+
         results[index] = Some(true);
 
         if motivation.no_longer_satisfied(&failed) {
@@ -57,6 +91,17 @@ pub(super) fn run(instructions: &Vec<(&ModuleInstructions, &Module, &ModuleMotiv
             }
         };
 
+        // run hook before removal
+        if instruction.new.is_none() {
+            if let Some(hook) = instruction.old.as_ref().and_then(|m| m.hooks.pre_remove.as_ref()) {
+                if let Err(e) = run_hook(hook, source, &cache, &mut workers) {
+                    error!("Fatal error occurred whilst applying modules: {e}");
+                    results[index] = Some(false);
+                    break 'install;
+                }
+            }
+        }
+
         // revert revertible changes
         if let Some(module) = &instruction.old {
             debug!("Reverting old changes");
@@ -64,6 +109,7 @@ pub(super) fn run(instructions: &Vec<(&ModuleInstructions, &Module, &ModuleMotiv
                 .zip(&instruction.revert)
                 .filter_map(|(j, exec)| if *exec { Some(j) } else { None }).collect();
 
+            reverted[index] = true;
             match revert_jobs(&jobs, &cache, &mut workers) {
                 Ok(true) => {}
                 Ok(false) => {
@@ -81,11 +127,23 @@ pub(super) fn run(instructions: &Vec<(&ModuleInstructions, &Module, &ModuleMotiv
         if let Some(module) = &instruction.new {
             debug!("Applying new changes");
 
-            let jobs: Vec<&BuiltJob> = module.jobs.iter()
+            // run hook before installation, as part of the jobs so its failure cancels the installation
+            let pre_install = if instruction.old.is_none() { module.hooks.pre_install.as_ref() } else { None };
+
+            let (jobs, batches): (Vec<&BuiltJob>, Vec<bool>) = pre_install.map(|h| (h, false)).into_iter().chain(module.jobs.iter()
                 .zip(&instruction.apply)
-                .filter_map(|(j, exec)| if *exec { Some(j) } else { None }).collect();
+                .zip(&batched[index])
+                .filter_map(|((j, exec), batched)| if *exec { Some((j, *batched)) } else { None })).unzip();
+
+            let mut packages = vec![];
+            let result = apply_jobs(&jobs, &batches, &present, &cache, &mut workers, &mut packages);
 
-            match apply_jobs(&jobs, &cache, &mut workers) {
+            // packages installed on their own are removed again like batched ones, if the module fails
+            for change in packages {
+                plan::record(&mut installs, index, change);
+            }
+
+            match result {
                 Ok(true) => {}
                 Ok(false) => {
                     error!("Apply steps for module {} did not go gracefully, removing its dependencies again", source.qualifier.unique());
@@ -118,15 +176,112 @@ pub(super) fn run(instructions: &Vec<(&ModuleInstructions, &Module, &ModuleMotiv
                 }
             }
         }
+
+        // refresh unchanged jobs after other jobs of the module changed
+        if results[index] == Some(true) {
+            match refresh_jobs(&refreshed(instruction), &cache, &mut workers) {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!("Refreshing unchanged jobs of module {} did not go gracefully", source.qualifier.unique())
+                }
+                Err(e) => {
+                    error!("Fatal error occurred whilst applying modules: {e}");
+                    break 'install;
+                }
+            }
+        }
+
+        // run hooks after successful installation or update
+        if results[index] == Some(true) {
+            let hook = match (&instruction.old, &instruction.new) {
+                (None, Some(new)) => new.hooks.post_install.as_ref(),
+                (Some(_), Some(new)) if instruction.apply.iter().chain(&instruction.revert).any(|b| *b) => new.hooks.post_update.as_ref(),
+                _ => None
+            };
+
+            if let Some(hook) = hook {
+                if let Err(e) = run_hook(hook, source, &cache, &mut workers) {
+                    error!("Fatal error occurred whilst applying modules: {e}");
+                    break 'install;
+                }
+            }
+        }
+    }
+
+    // packages which are no longer needed are removed in batches after everything else
+    let others: Vec<&BuiltModule> = cache.index.modules.iter()
+        .filter(|m| !instructions.iter().any(|(_, source, _)| source.qualifier == m.module.qualifier))
+        .map(|m| &m.built)
+        .collect();
+
+    let mut removals = plan::removals(&modules, &others, &installs, &reverted, &results);
+
+    if !removals.is_empty() {
+        info!("Removing packages...");
+    }
+
+    for batch in &mut removals {
+        run_batch(batch, instructions, cache, &mut workers, false)?;
+
+        for owner in batch.owners.iter().filter(|o| !o.done) {
+            let (_, source, _) = instructions[owner.module];
+            warn!("Failed to remove the packages of module {}, they may be left on your system", source.qualifier.unique());
+        }
     }
 
     Ok(results)
 }
 
-/// Applies a list of jobs
-fn apply_jobs(jobs: &[&BuiltJob], cache :&Path, portal: &mut WorkerPortal) -> anyhow::Result<bool> {
+/// Installs or removes a batch of packages, retrying the packages of every module on its own if it fails, so the failure is attributed to the right modules
+fn run_batch(batch: &mut Batch, instructions: &[(&ModuleInstructions, &Module, &ModuleMotivation)], cache: &Cache, portal: &mut WorkerPortal, apply: bool) -> anyhow::Result<()> {
+    // package changes do not use the cache, but are run in the cache of a participating module
+    let (_, module, _) = instructions[batch.owners[0].module];
+    let cache = cache.get_module_cache(module)?;
+
+    let change = batch.change(batch.packages());
+    debug!("Dispatching batched change '{}', root: {}", change.describe(), batch.manager.root);
+
+    match portal.dispatch(&change, batch.manager.root, &cache, apply)? {
+        Ok(()) => {
+            batch.owners.iter_mut().for_each(|o| o.done = true);
+            return Ok(())
+        }
+        Err(e) if batch.owners.len() == 1 => {
+            process_change_error(&change, batch.manager.root, e, apply);
+            return Ok(())
+        }
+        Err(_) => {
+            debug!("Batch failed, processing the packages of every module on its own");
+        }
+    }
+
+    for index in 0..batch.owners.len() {
+        let change = batch.change(batch.owners[index].packages.clone());
+
+        match portal.dispatch(&change, batch.manager.root, &cache, apply)? {
+            Ok(()) => batch.owners[index].done = true,
+            Err(e) => process_change_error(&change, batch.manager.root, e, apply)
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a hook of a module, failures are only reported
+fn run_hook(hook: &BuiltJob, source: &Module, cache: &Path, portal: &mut WorkerPortal) -> anyhow::Result<()> {
+    debug!("{}", hook.title);
+
+    if !apply_changes(&hook.changes, hook.root, None, cache, portal, &mut vec![])? {
+        warn!("Hook of module {} did not go gracefully", source.qualifier.unique());
+    }
+
+    Ok(())
+}
+
+/// Applies a list of jobs, whose packages are either installed in batches or on their own, collecting the latter
+fn apply_jobs<'a>(jobs: &[&'a BuiltJob], batched: &[bool], present: &[&PackageChange], cache :&Path, portal: &mut WorkerPortal, packages: &mut Vec<&'a PackageChange>) -> anyhow::Result<bool> {
     for (index, job) in jobs.iter().enumerate() {
-        let result = apply_changes(&job.changes, job.root, cache, portal)?;
+        let result = apply_changes(&job.changes, job.root, (!batched[index]).then_some(present), cache, portal, packages)?;
 
         if !result {
             debug!("Reverting previous jobs");
@@ -138,9 +293,45 @@ fn apply_jobs(jobs: &[&BuiltJob], cache :&Path, portal: &mut WorkerPortal) -> an
     Ok(true)
 }
 
-/// Applies a list of changes
-fn apply_changes(changes: &[Box<dyn AtomicChange>], root: bool, cache: &Path, portal: &mut WorkerPortal) -> anyhow::Result<bool> {
+/// Returns the unchanged jobs of an update which have to be refreshed.
+/// Every applied or reverted job of the module counts as a change, no matter what kind of job it is, changes to hooks do not.
+fn refreshed(instruction: &ModuleInstructions) -> Vec<&BuiltJob> {
+    if instruction.old.is_none() || !instruction.apply.iter().chain(&instruction.revert).any(|b| *b) {
+        return vec![]
+    }
+
+    instruction.new.iter()
+        .flat_map(|m| m.jobs.iter().zip(&instruction.apply))
+        .filter_map(|(j, exec)| if !*exec && !j.refresh.is_empty() { Some(j) } else { None })
+        .collect()
+}
+
+/// Applies the refresh changes of a list of jobs, failures are only reported
+fn refresh_jobs(jobs: &[&BuiltJob], cache: &Path, portal: &mut WorkerPortal) -> anyhow::Result<bool> {
+    let mut graceful = true;
+
+    for job in jobs {
+        debug!("Refreshing job '{}'", job.title);
+        if !apply_changes(&job.refresh, job.root, None, cache, portal, &mut vec![])? { graceful = false }
+    }
+
+    Ok(graceful)
+}
+
+/// Applies a list of changes, collecting the packages installed on their own.
+/// Packages are installed in batches, unless the packages which are already present are given.
+fn apply_changes<'a>(changes: &'a [Box<dyn AtomicChange>], root: bool, present: Option<&[&PackageChange]>, cache: &Path, portal: &mut WorkerPortal, packages: &mut Vec<&'a PackageChange>) -> anyhow::Result<bool> {
     for (index, change) in changes.iter().enumerate() {
+        let inline;
+        let change = match (change.package(), present) {
+            (Some(_), None) => continue,
+            (Some(package), Some(present)) => {
+                inline = plan::inline(package, present);
+                &inline
+            }
+            (None, _) => change
+        };
+
         debug!("Dispatching change '{}', root: {root}", change.describe());
 
         let result = portal.dispatch(change, root, cache, true)?;
@@ -151,6 +342,8 @@ fn apply_changes(changes: &[Box<dyn AtomicChange>], root: bool, cache: &Path, po
             revert_changes(&changes[0..index], root, cache, portal)?;
             return Ok(false)
         }
+
+        packages.extend(changes[index].package());
     }
 
     Ok(true)
@@ -173,6 +366,9 @@ fn revert_changes(changes: &[Box<dyn AtomicChange>], root: bool, cache: &Path, p
     let mut graceful = true;
 
     for change in changes.iter().rev() {
+        // packages are removed in batches
+        if change.package().is_some() { continue }
+
         debug!("Dispatching reversal of change '{}', root: {root}", change.describe());
 
         let result = portal.dispatch(change, root, cache, false)?;
@@ -195,3 +391,47 @@ fn process_change_error(change: &Box<dyn AtomicChange>, root: bool, error: Chang
         warn!("Change may have left unwanted traces on your system.");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+    use crate::jobs::BuiltJob;
+    use crate::jobs::hooks::BuiltHooks;
+    use crate::module::change::RunChange;
+    use crate::module::install::build::{BuiltModule, ModuleInstructions};
+    use crate::module::install::run::refreshed;
+    use crate::variables::Variable;
+
+    fn module() -> BuiltModule {
+        let mut config = BuiltJob::new();
+        config.title = "config".into();
+
+        let mut service = BuiltJob::new();
+        service.title = "service".into();
+        service.refresh.push(Box::new(RunChange::new("restart".into(), None, PathBuf::new(), false, false)));
+
+        BuiltModule { jobs: vec![config, service], hooks: BuiltHooks::default(), used_variables: Variable::base(), time: SystemTime::now() }
+    }
+
+    #[test]
+    fn refresh() {
+        let titles = |instruction: &ModuleInstructions| refreshed(instruction).iter().map(|j| j.title.clone()).collect::<Vec<_>>();
+
+        // another job of the module changed
+        let update = ModuleInstructions { apply: vec![true, false], revert: vec![true, false], new: Some(module()), old: Some(module()) };
+        assert_eq!(titles(&update), vec!["service"]);
+
+        // the job itself changed, so it is applied anyway
+        let update = ModuleInstructions { apply: vec![true, true], revert: vec![false, true], new: Some(module()), old: Some(module()) };
+        assert!(titles(&update).is_empty());
+
+        // nothing changed
+        let update = ModuleInstructions { apply: vec![false, false], revert: vec![false, false], new: Some(module()), old: Some(module()) };
+        assert!(titles(&update).is_empty());
+
+        // a fresh installation applies everything
+        let install = ModuleInstructions { apply: vec![true, false], revert: vec![], new: Some(module()), old: None };
+        assert!(titles(&install).is_empty());
+    }
+}
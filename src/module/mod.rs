@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::path::{Path, PathBuf};
@@ -7,9 +8,13 @@ use chksum::hash::SHA1;
 use log::debug;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::jobs::Job;
+use crate::jobs::hooks::ModuleHooks;
+use crate::jobs::{order_jobs, Job};
+use crate::module::overlay::Overlay;
 use crate::module::qualifier::ModuleQualifier;
 use crate::module::repository::Repository;
+use crate::variables::context::read_expression;
+use crate::variables::evaluate::{evaluate_condition, VariableEvalCounter};
 use crate::variables::Variable;
 use crate::registry::index::Indexable;
 
@@ -18,9 +23,10 @@ pub mod qualifier;
 pub mod change;
 pub mod install;
 pub mod host;
+pub mod overlay;
 
 /// File declaring the module config
-const MODULE_CONFIG: &str = "module.yml";
+pub const MODULE_CONFIG: &str = "module.yml";
 
 #[derive(Deserialize, JsonSchema)]
 #[schemars(title = "Module", deny_unknown_fields)]
@@ -45,21 +51,45 @@ pub struct ModuleConfig {
     /// precedence this module has when compared to other modules, mainly used for injections
     precedence: Option<u32>,
 
-    /// list of jobs to install
+    /// module this module inherits jobs, variables and injections from
+    extends: Option<String>,
+    /// condition as a variable expression, the module can only be installed where it evaluates to true
+    when: Option<String>,
+
+    /// list of jobs to install, jobs with the same title as a job of the extended module replace it
+    #[serde(default)]
     jobs: Vec<Job>,
 
+    /// commands or scripts which run once before or after the jobs of the module
+    hooks: Option<ModuleHooks>,
+
     /// variables used only for this module
     variables: Option<Variable>,
 
     /// variables injected into the global variable tree when this module is installed
-    injections: Option<Variable>
+    injections: Option<Variable>,
+
+    /// instances of this module which are installed independently, with variables overriding the module variables
+    instances: Option<HashMap<String, Variable>>
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Module {
     pub path: PathBuf,
+    /// directories of applied overlays, whose resources take precedence over the module's own
+    #[serde(default)]
+    pub overlays: Vec<PathBuf>,
+    /// resource directories of the extended module, which are searched after the module's own
+    #[serde(default)]
+    pub inherited: Vec<PathBuf>,
+    /// qualifier of the module this module extends, which is not yet resolved
+    #[serde(skip)]
+    pub extends: Option<String>,
     pub qualifier: ModuleQualifier,
     pub dependencies: Vec<String>,
+    /// condition under which this module applies to a system
+    #[serde(default)]
+    pub when: Option<String>,
     checksum: String,
 
     pub name: String,
@@ -68,8 +98,16 @@ pub struct Module {
     pub version: String,
 
     jobs: Vec<Job>,
+    #[serde(default)]
+    pub hooks: ModuleHooks,
 
     variables: Option<Variable>,
+    /// variables of the instance, if this module is an instance
+    #[serde(default)]
+    parameters: Option<Variable>,
+    /// instances declared by the module, which are not yet instantiated
+    #[serde(skip)]
+    instances: HashMap<String, Variable>,
 
     pub injections: Option<Variable>,
     pub precedence: Option<u32>
@@ -105,9 +143,13 @@ impl Module {
 
         Ok(Some(Self {
             path: directory.to_owned(),
+            overlays: vec![],
+            inherited: vec![],
+            extends: config.extends,
             qualifier,
             checksum,
             dependencies,
+            when: config.when,
             name: config.name,
             description: config.description,
             author: config.author,
@@ -115,14 +157,134 @@ impl Module {
             precedence: config.precedence,
 
             jobs: config.jobs,
+            hooks: config.hooks.unwrap_or_default(),
             variables: config.variables,
+            parameters: None,
+            instances: config.instances.unwrap_or_default(),
             injections: config.injections
         }))
     }
 
+    /// Creates a module for every declared instance, or returns the module itself if it has none
+    pub fn instantiate(mut self) -> anyhow::Result<Vec<Self>> {
+        if self.instances.is_empty() { return Ok(vec![self]) }
+
+        let mut instances: Vec<(String, Variable)> = std::mem::take(&mut self.instances).into_iter().collect();
+        instances.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        instances.into_iter()
+            .map(|(name, parameters)| {
+                let qualifier = self.qualifier.with_instance(name);
+                if !qualifier.legal() {
+                    return Err(anyhow!("Instance qualifier '{}' contains illegal characters", qualifier.unique()));
+                }
+
+                Ok(Self {
+                    qualifier,
+                    parameters: Some(parameters),
+                    ..self.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Evaluates whether the module applies to a system with the given variables
+    pub fn applies(&self, variables: &Variable) -> anyhow::Result<bool> {
+        let Some(condition) = &self.when else { return Ok(true) };
+
+        read_expression(condition)
+            .and_then(|expression| evaluate_condition(&expression, variables, &mut VariableEvalCounter::default()))
+            .map_err(|e| {
+                e.print(&self.path.join(MODULE_CONFIG).to_string_lossy(), condition);
+                anyhow!("Failed to evaluate the condition of module {}", self.qualifier.unique())
+            })
+    }
+
+    /// Returns the variables of the module, with the instance variables merged over them
+    pub fn variables(&self) -> Variable {
+        let mut variables = self.variables.clone().unwrap_or_else(Variable::base);
+        if let Some(parameters) = &self.parameters {
+            variables.merge(parameters);
+        }
+
+        variables
+    }
+
     pub fn equals_jobs(&self, other: &Self) -> bool {
         self.jobs == other.jobs
     }
+
+    /// Orders the jobs of the module by their declared predecessors
+    pub fn order_jobs(&mut self) -> anyhow::Result<()> {
+        self.jobs = order_jobs(std::mem::take(&mut self.jobs))?;
+        Ok(())
+    }
+
+    /// Applies an overlay onto this module, overlays applied later take precedence
+    pub fn apply_overlay(&mut self, overlay: &Overlay) {
+        debug!("Applying overlay '{}' onto module {}", overlay.path.to_string_lossy(), self.qualifier.unique());
+
+        self.overlays.insert(0, overlay.path.clone());
+        self.checksum = combine_checksums(&self.checksum, &overlay.checksum);
+
+        self.jobs.extend(overlay.jobs.iter().cloned());
+
+        if let Some(variables) = &overlay.variables {
+            self.variables.get_or_insert_with(Variable::base).merge(variables);
+        }
+        if let Some(injections) = &overlay.injections {
+            self.injections.get_or_insert_with(Variable::base).merge(injections);
+        }
+    }
+
+    /// Inherits jobs, variables and injections from an already resolved base module
+    pub fn inherit(&mut self, base: &Module) {
+        debug!("Module {} inherits from module {}", self.qualifier.unique(), base.qualifier.unique());
+
+        self.extends = None;
+        self.inherited = base.resource_dirs();
+        self.checksum = combine_checksums(&self.checksum, &base.checksum);
+
+        // replace jobs with the same title, append the others
+        let mut jobs = base.jobs.clone();
+        for job in self.jobs.drain(..) {
+            if let Some(position) = jobs.iter().position(|j| job.overrides(j)) {
+                jobs[position] = job;
+            } else {
+                jobs.push(job);
+            }
+        }
+        self.jobs = jobs;
+        self.hooks.inherit(&base.hooks);
+
+        self.variables = inherit_variables(&base.variables, &self.variables);
+        self.injections = inherit_variables(&base.injections, &self.injections);
+    }
+
+    /// Returns the directories resources of this module are searched in, ordered by precedence
+    pub fn resource_dirs(&self) -> Vec<PathBuf> {
+        self.overlays.iter().cloned()
+            .chain(std::iter::once(self.path.clone()))
+            .chain(self.inherited.iter().cloned())
+            .collect()
+    }
+}
+
+/// Merges the variables of a module over the ones of its base module
+fn inherit_variables(base: &Option<Variable>, own: &Option<Variable>) -> Option<Variable> {
+    match (base, own) {
+        (Some(base), Some(own)) => {
+            let mut merged = base.clone();
+            merged.merge(own);
+            Some(merged)
+        }
+        (base, own) => own.clone().or_else(|| base.clone())
+    }
+}
+
+/// Combines two checksums into one, so a change in either one is noticed
+fn combine_checksums(first: &str, second: &str) -> String {
+    chksum::hash::hash::<SHA1, _>(format!("{first}{second}")).to_hex_lowercase()
 }
 
 impl Indexable for Module {
@@ -0,0 +1,80 @@
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context};
+use chksum::chksum;
+use chksum::hash::SHA1;
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use crate::jobs::Job;
+use crate::module::repository::Repository;
+use crate::variables::Variable;
+
+/// File declaring an overlay config
+pub const OVERLAY_CONFIG: &str = "overlay.yml";
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "Overlay", deny_unknown_fields)]
+pub struct OverlayConfig {
+    /// qualifier of the module this overlay modifies
+    target: String,
+
+    /// jobs which are appended to the jobs of the module
+    jobs: Option<Vec<Job>>,
+
+    /// variables merged over the variables of the module
+    variables: Option<Variable>,
+
+    /// variables merged over the injections of the module
+    injections: Option<Variable>
+}
+
+/// An overlay modifies a module of any repository without forking it.
+/// Resources inside the overlay directory take precedence over the resources of the module.
+pub struct Overlay {
+    pub path: PathBuf,
+    pub repository: String,
+    pub checksum: String,
+
+    target: String,
+
+    pub jobs: Vec<Job>,
+    pub variables: Option<Variable>,
+    pub injections: Option<Variable>
+}
+
+impl Overlay {
+
+    pub fn try_load(directory: &Path, parent: &Repository) -> anyhow::Result<Option<Self>> {
+        let config = directory.join(OVERLAY_CONFIG);
+        if !config.is_file() { return Ok(None); }
+
+        debug!("Loading overlay from '{}'", config.to_string_lossy());
+
+        let config: OverlayConfig = serde_yaml::from_reader(File::open(&config).context("Failed to open overlay file")?)
+            .map_err(|f| anyhow!("Failed to read overlay file ({})", f.to_string()))?;
+
+        // Calculate current checksum
+        let dir = fs::read_dir(directory).context("Failed to read dir for checksum")?;
+        let checksum = chksum::<SHA1, _>(dir).context("Failed to calculate checksum")?.to_hex_lowercase();
+
+        Ok(Some(Self {
+            path: directory.to_owned(),
+            repository: parent.name.clone(),
+            checksum,
+
+            target: config.target,
+
+            jobs: config.jobs.unwrap_or_default(),
+            variables: config.variables,
+            injections: config.injections
+        }))
+    }
+
+    /// Returns the unique qualifier of the targeted module, modules without a repository are searched in the own repository
+    pub fn target(&self) -> String {
+        if self.target.contains('/') { self.target.clone() }
+        else { format!("{}/{}", self.repository, self.target) }
+    }
+}
@@ -2,6 +2,9 @@ use std::fmt::{Debug, Formatter, Write};
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 
+/// Separates the module name from its instance inside a qualifier
+const INSTANCE_SEPARATOR: char = '@';
+
 #[derive(Serialize, Deserialize, Clone, Eq, Hash)]
 pub struct ModuleQualifier {
     repository: String,
@@ -10,7 +13,10 @@ pub struct ModuleQualifier {
     /// Alias defined in the config
     alias: Option<String>,
     /// Provides defined in the config
-    provide: Option<String>
+    provide: Option<String>,
+    /// Instance of the module, if it is instantiated multiple times
+    #[serde(default)]
+    instance: Option<String>
 }
 
 impl ModuleQualifier {
@@ -20,10 +26,16 @@ impl ModuleQualifier {
             repository,
             dir: path.file_name().map(|os| os.to_string_lossy().to_string()).expect("This can not happen because a module folder always has a name"),
             alias,
-            provide
+            provide,
+            instance: None
         }
     }
 
+    /// Returns the qualifier for an instance of the same module
+    pub fn with_instance(&self, instance: String) -> Self {
+        Self { instance: Some(instance), ..self.clone() }
+    }
+
     /// Returns the repository of the qualifier
     pub fn repository(&self) -> &String {
         &self.repository
@@ -38,12 +50,31 @@ impl ModuleQualifier {
         }
 
         // Is the module
-        self.name() == qualifier || self.unique() == qualifier
+        self.matches(qualifier)
+    }
+
+    /// Returns whether the module is matched by a normal or unique qualifier, which can specify an instance
+    pub fn matches(&self, query: &str) -> bool {
+        let (query, instance) = match query.split_once(INSTANCE_SEPARATOR) {
+            Some((query, instance)) => (query, Some(instance)),
+            None => (query, None)
+        };
+
+        let module = if query.contains('/') {
+            query.split_once('/').is_some_and(|(repository, name)| *self.repository == *repository && self.name() == name)
+        } else {
+            self.name() == query
+        };
+
+        module && instance.is_none_or(|i| self.instance.as_deref() == Some(i))
     }
 
     // Returns the full qualifier of that module
     pub fn unique(&self) -> String {
-        format!("{}/{}", &self.repository, self.name())
+        match &self.instance {
+            Some(instance) => format!("{}/{}{INSTANCE_SEPARATOR}{instance}", self.repository, self.name()),
+            None => format!("{}/{}", &self.repository, self.name())
+        }
     }
 
     /// Returns qualifying name for module
@@ -63,7 +94,9 @@ impl ModuleQualifier {
     /// Checks the module name and insures that it does not mess with the filesystem during caching
     pub fn legal(&self) -> bool {
         let name = self.name();
-        !name.is_empty() && !name.contains('/')
+        let legal = |s: &str| !s.is_empty() && !s.contains('/') && !s.contains(INSTANCE_SEPARATOR);
+
+        legal(name) && self.instance.as_deref().is_none_or(legal)
     }
 }
 
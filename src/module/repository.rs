@@ -4,11 +4,13 @@ use std::fs;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use anyhow::{anyhow, Context};
+use glob::{MatchOptions, Pattern};
 use log::warn;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::module::host::{Host, HOST_CONFIG_FILEENDING};
-use crate::module::Module;
+use crate::module::{Module, MODULE_CONFIG};
+use crate::module::overlay::{Overlay, OVERLAY_CONFIG};
 use crate::variables::Variable;
 
 pub const REPOSITORY_CONFIG: &str = "pusta.yml";
@@ -18,6 +20,14 @@ pub const REPOSITORY_CONFIG: &str = "pusta.yml";
 pub struct RepositoryConfig {
     pub alias: Option<String>,
 
+    /// aliases of other repositories this repository builds upon, in order
+    pub requires: Option<Vec<String>>,
+
+    /// glob patterns of module directories to load, relative to the repository, by default every module is loaded
+    pub modules: Option<Vec<String>>,
+    /// glob patterns of directories which are never searched for modules
+    pub ignore: Option<Vec<String>>,
+
     pub variables: Option<Variable>
 }
 
@@ -53,21 +63,36 @@ impl Repository {
     }
 
     pub fn load_modules(&self) -> anyhow::Result<Vec<Module>> {
-        let mut modules = vec![];
+        let config = self.load_config()?;
+        let include = compile_patterns(config.modules.as_deref().unwrap_or_default())?;
+        let ignore = compile_patterns(config.ignore.as_deref().unwrap_or_default())?;
 
-        for entry in fs::read_dir(&self.location)? {
-            let entry = entry?.path();
+        let mut directories = vec![];
+        find_directories(&self.location, Path::new(""), MODULE_CONFIG, &ignore, &mut directories)?;
+        directories.sort();
 
-            match Module::try_load(&entry, &self) {
-                Ok(Some(m)) => {
-                    if modules.iter().any(|n: &Module| n.qualifier == m.qualifier) {
-                        warn!("Refused to load {}/'{}', since its qualifier is already taken.", self.name, entry.file_name().map(OsStr::to_string_lossy).unwrap_or(Cow::Borrowed("unknown module")));
-                    }
+        let mut modules: Vec<Module> = vec![];
+
+        for (entry, relative) in directories {
+            if !include.is_empty() && !include.iter().any(|p| p.matches_path_with(&relative, PATTERN_OPTIONS)) {
+                continue;
+            }
 
-                    modules.push(m);
+            match Module::try_load(&entry, self).and_then(|m| m.map(Module::instantiate).transpose()) {
+                Ok(Some(instances)) => {
+                    for m in instances {
+                        if let Some(other) = modules.iter().find(|n| n.qualifier == m.qualifier) {
+                            return Err(anyhow!("Module qualifier '{}' is used by both '{}' and '{}'",
+                                m.qualifier.unique(),
+                                other.path.strip_prefix(&self.location).unwrap_or(&other.path).to_string_lossy(),
+                                relative.to_string_lossy()));
+                        }
+
+                        modules.push(m);
+                    }
                 }
                 Err(e) => {
-                    warn!("Failed to load {}/'{}': {e:#}", self.name, entry.file_name().map(OsStr::to_string_lossy).unwrap_or(Cow::Borrowed("unknown module")));
+                    warn!("Failed to load {}/'{}': {e:#}", self.name, relative.to_string_lossy());
                 }
                 _ => {}
             }
@@ -76,6 +101,29 @@ impl Repository {
         Ok(modules)
     }
 
+    /// loads all overlays of the repository, which modify modules of this or other repositories
+    pub fn load_overlays(&self) -> anyhow::Result<Vec<Overlay>> {
+        let ignore = compile_patterns(self.load_config()?.ignore.as_deref().unwrap_or_default())?;
+
+        let mut directories = vec![];
+        find_directories(&self.location, Path::new(""), OVERLAY_CONFIG, &ignore, &mut directories)?;
+        directories.sort();
+
+        Ok(
+            directories.into_iter()
+                .filter_map(|(path, relative)| {
+                    match Overlay::try_load(&path, self) {
+                        Ok(overlay) => overlay,
+                        Err(e) => {
+                            warn!("Failed to load overlay {}/'{}': {e:#}", self.name, relative.to_string_lossy());
+                            None
+                        }
+                    }
+                })
+                .collect()
+        )
+    }
+
     /// loads all host files of the repository
     pub fn load_hosts(&self) -> anyhow::Result<Vec<Host>> {
         Ok(
@@ -97,14 +145,60 @@ impl Repository {
 
     /// Loads the variables from the repository config
     pub fn load_variables(&self) -> anyhow::Result<Option<Variable>> {
+        Ok(self.load_config()?.variables)
+    }
+
+    /// Loads the aliases of the repositories this repository requires
+    pub fn load_requirements(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.load_config()?.requires.unwrap_or_default())
+    }
+
+    /// Reads the current repository config file
+    pub fn load_config(&self) -> anyhow::Result<RepositoryConfig> {
         let path = self.location.join(REPOSITORY_CONFIG);
 
-        let config: RepositoryConfig = serde_yaml::from_reader(
-            File::open(&path).with_context(|| format!("failed to open repository config file for {}", &self.name))?
-        ).map_err(|e| anyhow!("failed to parse repository config file for {}: {e:#}", &self.name))?;
+        serde_yaml::from_reader(
+            File::open(&path).with_context(|| format!("failed to open repository config file for {}", self.name))?
+        ).map_err(|e| anyhow!("failed to parse repository config file for {}: {e:#}", self.name))
+    }
+}
+
+/// Options used when matching module directories against patterns, a `*` does not cross directories
+const PATTERN_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Compiles the glob patterns provided in the repository config
+fn compile_patterns(patterns: &[String]) -> anyhow::Result<Vec<Pattern>> {
+    patterns.iter()
+        .map(|p| Pattern::new(p).with_context(|| format!("invalid glob pattern '{p}' in repository config")))
+        .collect()
+}
+
+/// Recursively collects all directories containing the given config file, together with their path relative to the repository.
+/// Modules, overlays, hidden, symlinked and ignored directories are not searched further.
+fn find_directories(directory: &Path, relative: &Path, config: &str, ignore: &[Pattern], found: &mut Vec<(PathBuf, PathBuf)>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
 
-        Ok(config.variables)
+        if !path.is_dir() || ignore.iter().any(|p| p.matches_path_with(&relative, PATTERN_OPTIONS)) {
+            continue;
+        }
+
+        if path.join(config).is_file() {
+            found.push((path, relative));
+        } else if path.join(MODULE_CONFIG).is_file() || path.join(OVERLAY_CONFIG).is_file() {
+            continue;
+        } else if !entry.file_type()?.is_symlink() && !entry.file_name().to_string_lossy().starts_with('.') {
+            find_directories(&path, &relative, config, ignore, found)?;
+        }
     }
+
+    Ok(())
 }
 
 /// Validates repository name and insures that it does not mess with the filesystem during caching
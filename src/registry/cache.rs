@@ -158,6 +158,30 @@ impl Cache {
         self.repositories.iter().find(|r| r.name == name)
     }
 
+    /// Resolves the repositories a repository builds upon, ordered so that every repository comes after the ones it requires.
+    /// The chain ends with the repository itself, repositories which are not added are skipped.
+    pub fn get_repository_chain<'a>(&'a self, repository: &'a Repository) -> Vec<&'a Repository> {
+        let mut visited = vec![];
+        let mut chain = vec![];
+
+        self.visit_repository(repository, &mut visited, &mut chain);
+        chain
+    }
+
+    /// Visits a repository in post order whilst resolving the repository chain
+    fn visit_repository<'a>(&'a self, repository: &'a Repository, visited: &mut Vec<String>, chain: &mut Vec<&'a Repository>) {
+        if visited.contains(&repository.name) { return }
+        visited.push(repository.name.clone());
+
+        for required in repository.load_requirements().unwrap_or_default() {
+            if let Some(required) = self.get_repository(&required) {
+                self.visit_repository(required, visited, chain);
+            }
+        }
+
+        chain.push(repository);
+    }
+
 
     /// Creates a module cache folder for the job cache of the modules
     pub fn get_module_cache(&self, module: &Module) -> anyhow::Result<PathBuf> {
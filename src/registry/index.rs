@@ -1,3 +1,5 @@
+use anyhow::anyhow;
+use log::warn;
 use crate::module::Module;
 use crate::module::qualifier::ModuleQualifier;
 use crate::module::repository::Repository;
@@ -26,13 +28,7 @@ impl<T> Index<T> where T: Indexable {
     pub fn query(&self, query: &str) -> Vec<&T> {
 
         self.modules.iter()
-            .filter(|m| {
-                if query.contains('/') {
-                    m.qualifier().unique() == query
-                } else {
-                    m.qualifier().name() == query
-                }
-            })
+            .filter(|m| m.qualifier().matches(query))
             .collect()
 
     }
@@ -79,6 +75,11 @@ impl<T> Index<T> where T: Indexable {
         self.modules.iter().find(|m| m.qualifier() == qualifier)
     }
 
+    /// Returns a mutable module for the given qualifier
+    pub fn get_mut(&mut self, qualifier: &ModuleQualifier) -> Option<&mut T> {
+        self.modules.iter_mut().find(|m| m.qualifier() == qualifier)
+    }
+
     /// Adds a module or replaces a given one if needed
     pub fn add(&mut self, module: T) {
         // Remove possible duplicates
@@ -107,4 +108,76 @@ impl Index<Module> {
     pub fn remove_repository(&mut self, repo: &Repository) {
         self.modules.retain(|r| *r.qualifier.repository() != repo.name);
     }
+
+    /// Resolves the base modules of all modules extending another one, modules whose base cannot be resolved are unloaded
+    pub fn resolve_extensions(&mut self) {
+        let pending: Vec<ModuleQualifier> = self.modules.iter()
+            .filter(|m| m.extends.is_some())
+            .map(|m| m.qualifier.clone())
+            .collect();
+
+        for qualifier in pending {
+            // module may have already been unloaded because its base failed
+            if self.get(&qualifier).is_none() { continue }
+
+            if let Err(e) = self.resolve_extension(&qualifier, &mut vec![]) {
+                warn!("Failed to resolve the base of module {}, unloading it: {e}", qualifier.unique());
+                self.remove(&qualifier);
+            }
+        }
+    }
+
+    /// Orders the jobs of all modules, modules with an invalid job order are unloaded
+    pub fn order_jobs(&mut self) {
+        self.modules.retain_mut(|module| {
+            match module.order_jobs() {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Failed to order the jobs of module {}, unloading it: {e}", module.qualifier.unique());
+                    false
+                }
+            }
+        });
+    }
+
+    /// Resolves the base of a module, after its own base has been resolved
+    fn resolve_extension(&mut self, qualifier: &ModuleQualifier, stack: &mut Vec<ModuleQualifier>) -> anyhow::Result<()> {
+        let module = self.get(qualifier).ok_or_else(|| anyhow!("module {} is not loaded", qualifier.unique()))?;
+        let Some(extends) = module.extends.clone() else { return Ok(()) };
+
+        if stack.contains(qualifier) {
+            return Err(anyhow!("module {} extends itself", qualifier.unique()));
+        }
+
+        let base = self.find_base(&extends, qualifier.repository())?;
+
+        stack.push(qualifier.clone());
+        self.resolve_extension(&base, stack)?;
+        stack.pop();
+
+        let base = self.get(&base).cloned().ok_or_else(|| anyhow!("module {} is not loaded", base.unique()))?;
+        self.get_mut(qualifier).expect("module was just resolved").inherit(&base);
+
+        Ok(())
+    }
+
+    /// Finds the module extended under the given query, preferring modules of the same repository
+    fn find_base(&self, query: &str, repository: &str) -> anyhow::Result<ModuleQualifier> {
+        let modules = self.query(query);
+
+        let module = match modules.as_slice() {
+            [module] => *module,
+            [] => return Err(anyhow!("there is no module '{query}'")),
+            multiple => {
+                let local: Vec<_> = multiple.iter().filter(|m| m.qualifier.repository() == repository).collect();
+
+                match local.as_slice() {
+                    [module] => **module,
+                    _ => return Err(anyhow!("the module '{query}' is ambiguous, use a unique qualifier"))
+                }
+            }
+        };
+
+        Ok(module.qualifier.clone())
+    }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 pub mod index;
 pub mod cache;
 
+use std::collections::HashMap;
 use std::path::Path;
 use anyhow::anyhow;
 use chrono::{DateTime, Local};
@@ -18,7 +19,7 @@ use crate::output::logger::section;
 use crate::output::table::{table, Column};
 use crate::registry::cache::Cache;
 use crate::registry::index::{Index, Indexable};
-use crate::variables::{construct_host, construct_injected, generate_magic, load_system, Variable};
+use crate::variables::{construct_host, construct_injected, generate_magic, load_repositories, load_system, merge_variables, Variable};
 
 /// This struct handles all modules and modifies them. Essentially, every change in install state goes through this struct.
 pub struct Registry {
@@ -52,6 +53,37 @@ impl Registry {
             }
         }
 
+        // Apply overlays onto indexed modules
+        for repo in &self.cache.repositories {
+            match repo.load_overlays() {
+                Ok(overlays) => {
+                    for overlay in overlays {
+                        let target = overlay.target();
+                        let qualifiers: Vec<ModuleQualifier> = self.index.query(&target).iter().map(|m| m.qualifier.clone()).collect();
+
+                        if qualifiers.is_empty() {
+                            warn!("Overlay '{}' of repository '{}' targets the module '{target}', which could not be found", overlay.path.to_string_lossy(), repo.name);
+                        }
+
+                        // every instance of the module is modified
+                        for qualifier in qualifiers {
+                            self.index.get_mut(&qualifier).expect("module was just queried").apply_overlay(&overlay);
+                        }
+                    }
+                }
+                Err(e) => { warn!("Failed to load overlays of repository '{}': {e}", repo.name) }
+            }
+        }
+
+        // Resolve module inheritance
+        self.index.resolve_extensions();
+        self.index.order_jobs();
+
+        // Check repository requirements
+        for repo in &self.cache.repositories {
+            self.check_requirements(repo);
+        }
+
         // Index hosts
         for repo in &self.cache.repositories {
             match repo.load_hosts() {
@@ -96,9 +128,23 @@ impl Registry {
             Err(e) => { warn!("Failed to load modules from this repository: {e}"); }
         }
 
+        self.check_requirements(repository);
+
         info!("Successfully added repository")
     }
 
+    /// Warns about repositories required by the given one, which are not added
+    fn check_requirements(&self, repository: &Repository) {
+        match repository.load_requirements() {
+            Ok(requirements) => {
+                for required in requirements.iter().filter(|r| self.cache.get_repository(r).is_none()) {
+                    warn!("Repository '{}' builds upon the repository '{required}', which is not added", repository.name);
+                }
+            }
+            Err(e) => { warn!("Failed to read requirements of repository '{}': {e}", repository.name) }
+        }
+    }
+
     /// Removes a repository
     pub fn remove_repository(&mut self, name: &str) {
         info!("Removing source repository under alias '{name}'");
@@ -131,7 +177,7 @@ impl Registry {
             return;
         }
 
-        let mut gatherer = Gatherer::default();
+        let mut gatherer = Gatherer::new(self.inapplicable_modules(&self.module_environment()));
 
         if let Err(e) = gatherer.install(module, &self.cache.index, &self.index) {
             error!("{e}");
@@ -167,6 +213,51 @@ impl Registry {
         modify(gatherer, &self.index, &mut self.cache, &self.hosts, &self.config);
     }
 
+    /// Creates the environment indexed modules are evaluated in
+    fn module_environment(&self) -> ModuleEnvironment {
+        ModuleEnvironment {
+            magic_variables: generate_magic(),
+            system_variables: load_system(&self.config).unwrap_or(Variable::base()),
+            injected_variables: self.newest_injected_variables(),
+            host_variables: construct_host(&self.hosts),
+            package_config: self.config.system.package_manager.clone(),
+            package_managers: self.config.system.package_managers.clone(),
+            service_config: self.config.system.service_manager.clone(),
+            timer_config: self.config.system.timers.clone(),
+        }
+    }
+
+    /// Returns the qualifiers of all indexed modules which do not apply to this system
+    fn inapplicable_modules(&self, env: &ModuleEnvironment) -> Vec<ModuleQualifier> {
+        // the variables of every repository are only loaded once, and only if it has conditional modules
+        let mut repositories: HashMap<&str, Option<Vec<Variable>>> = HashMap::new();
+
+        self.index.modules.iter()
+            .filter(|module| {
+                if module.when.is_none() { return false }
+
+                let variables = repositories.entry(module.qualifier.repository()).or_insert_with(|| {
+                    let chain = self.cache.get_repository(module.qualifier.repository())
+                        .map(|repo| self.cache.get_repository_chain(repo))
+                        .unwrap_or_default();
+
+                    load_repositories(&chain).map_err(|e| error!("{e}")).ok()
+                });
+
+                let Some(variables) = variables else { return true };
+
+                match module.applies(&merge_variables(module, variables, env)) {
+                    Ok(applies) => !applies,
+                    Err(e) => {
+                        error!("{e}");
+                        true
+                    }
+                }
+            })
+            .map(|m| m.qualifier.clone())
+            .collect()
+    }
+
     pub fn newest_injected_variables(&self) -> Variable {
         let installed_newest = self.cache.index.modules.iter().map(|installed| {
             self.index.get(installed.qualifier()).unwrap_or(&installed.module) // orphaned are the newest already
@@ -179,18 +270,18 @@ impl Registry {
     pub fn update_everything(&mut self) {
         section("Looking for updates...");
 
-        let env = ModuleEnvironment {
-            magic_variables: generate_magic(),
-            system_variables: load_system(&self.config).unwrap_or(Variable::base()),
-            injected_variables: self.newest_injected_variables(),
-            host_variables: construct_host(&self.hosts),
-            package_config: Default::default(),
-        };
+        let env = self.module_environment();
+        let inapplicable = self.inapplicable_modules(&env);
 
         let updatable: Vec<ModuleQualifier> = self.cache.index.modules.iter().filter_map(|installed| {
 
             if let Some(indexed) = self.index.get(installed.qualifier()) {
                 if !installed.up_to_date(indexed, &env, &self.cache) {
+                    if inapplicable.contains(installed.qualifier()) {
+                        warn!("Skipping outdated module {}, as it no longer applies to this system", installed.qualifier().unique());
+                        return None
+                    }
+
                     info!("Found outdated module {}", installed.qualifier().unique());
                     return Some(installed.qualifier().clone())
                 }
@@ -219,6 +310,11 @@ impl Registry {
                 return true;
             };
 
+            if inapplicable.contains(module) {
+                warn!("Skipping host requirement {}, as it does not apply to this system", module.unique());
+                return false;
+            }
+
             // only keep modules which are not yet installed
             self.cache.index.get(module).is_none()
         }).collect::<anyhow::Result<Vec<_>>>();
@@ -233,7 +329,7 @@ impl Registry {
             return;
         }
 
-        let mut gatherer = Gatherer::default();
+        let mut gatherer = Gatherer::new(inapplicable);
         for q in updatable {
             if let Err(e) = gatherer.update(q, &self.cache.index, &self.index) {
                 error!("{e}");
@@ -274,7 +370,7 @@ impl Registry {
 
         // TODO: Check if outdated first
 
-        let mut gatherer = Gatherer::default();
+        let mut gatherer = Gatherer::new(self.inapplicable_modules(&self.module_environment()));
         if let Err(e) = gatherer.update(module, &self.cache.index, &self.index) {
             error!("{e}");
             return;
@@ -312,13 +408,8 @@ impl Registry {
         if self.cache.index.modules.is_empty() {
             info!("{}", "No modules are currently installed".italic().dimmed())
         } else {
-            let env = ModuleEnvironment {
-                magic_variables: generate_magic(),
-                system_variables: load_system(&self.config).unwrap_or(Variable::base()),
-                injected_variables: self.newest_injected_variables(),
-                host_variables: construct_host(&self.hosts),
-                package_config: Default::default(),
-            };
+            let env = self.module_environment();
+            let inapplicable = self.inapplicable_modules(&env);
 
             let required = self.hosts.iter().flat_map(|h| h.modules.iter().map(|q|
                 if q.contains('/') { q.clone() }
@@ -349,7 +440,9 @@ impl Registry {
 
             let rows = sorted.iter().map(|module| {
                 let info = if let Some(indexed) = self.index.get(&module.module.qualifier) {
-                    if !module.up_to_date(indexed, &env, &self.cache) {
+                    if inapplicable.contains(&module.module.qualifier) {
+                        "inapplicable".magenta()
+                    } else if !module.up_to_date(indexed, &env, &self.cache) {
                         "outdated".yellow()
                     } else {
                         "up-to-date".green()
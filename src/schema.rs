@@ -4,7 +4,7 @@ use colored::{ColoredString, Colorize};
 use log::{error, info};
 use schemars::{generate::SchemaSettings, JsonSchema, SchemaGenerator};
 
-use crate::{config::Config, module::{repository::RepositoryConfig, ModuleConfig}, output::table::{table, Column}};
+use crate::{config::Config, module::{overlay::OverlayConfig, repository::RepositoryConfig, ModuleConfig}, output::table::{table, Column}};
 
 pub const DEFAULT_PARENT: &str = "~/.local/share";
 pub const DEFAULT_DIR: &str = "/pusta/schemas";
@@ -56,6 +56,11 @@ pub fn write_schemas(directory: &String) {
         Err(err) => errors.push(format!("Failed to write repository schema: {err}")),
     }
 
+    match write_schema::<OverlayConfig>(&mut generator, &path,"overlay.json") {
+        Ok(_) => rows.push([ "Schema for overlay configurations".into(), format!("{directory}/overlay.json").dimmed() ]),
+        Err(err) => errors.push(format!("Failed to write overlay schema: {err}")),
+    }
+
     table(columns, rows, "  ");
 
     if !errors.is_empty() {
@@ -97,6 +97,35 @@ pub fn read_context(input: &str) -> Result<Context, VariableError> {
     Ok(context)
 }
 
+/// Reads a single expression, like a variable reference with modifiers or a literal, which makes up the whole input
+pub fn read_expression(input: &str) -> Result<Expression, VariableError> {
+    let token = read_token_at(input, 0)?;
+
+    let expression = match token.token {
+        TokenType::Variable { name, modifiers, name_range } => { to_variable_expression(token.range.clone(), name, modifiers, name_range)? }
+        TokenType::Literal { value } => { to_literal_expression(token.range.clone(), value) }
+        TokenType::Keyword { .. } => {
+            return Err(VariableError {
+                title: "expected variable reference or literal".into(),
+                primary: (token.range, "found keyword, expected variable reference or literal".into()),
+                secondary: vec![],
+                summary: "expressions cannot contain keywords".into()
+            })
+        }
+    };
+
+    if !input[token.range.end..].trim().is_empty() {
+        return Err(VariableError {
+            title: "expected end of expression".into(),
+            primary: (token.range.end..input.len(), "expected end of expression, found other token instead".into()),
+            secondary: vec![],
+            summary: "an expression must only contain a single token".into()
+        })
+    }
+
+    Ok(expression)
+}
+
 /// Reads a context from the specified starting position.
 /// Exits early if it encounters a rouge control flow keyword.
 ///     In these cases, it returns the keyword alongside the range where it has found it
@@ -98,6 +98,21 @@ fn evaluate_statement(input: &str, statement: &Statement, variables: &Variable,
     })
 }
 
+/// Evaluates an expression which is used as a condition and thus has to be a boolean
+pub fn evaluate_condition(expr: &Expression, variables: &Variable, counter: &mut VariableEvalCounter) -> Result<bool, VariableError> {
+    match evaluate_expression(expr, variables, counter)? {
+        Value::Boolean(b) => { Ok(b) }
+        v => {
+            Err(VariableError {
+                title: "expected boolean condition".to_string(),
+                primary: (expr.range.clone(), format!("expected condition of type boolean, found `{}`", v.type_name())),
+                secondary: vec![],
+                summary: "a condition has to be of type boolean".to_string(),
+            })
+        }
+    }
+}
+
 /// Evaluates the expression for a list. This should only be temporary, because modifiers should support lists and objects in the future too.
 fn evaluate_expression_for_list(expr: &Expression, variables: &Variable, counter: &mut VariableEvalCounter) -> Result<Vec<Variable>, VariableError> {
     match (&expr.content, expr.modifiers.is_empty()) {
@@ -5,6 +5,7 @@ pub mod modifier;
 
 use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::fs::File;
 use std::ops::Range;
 use std::path::PathBuf;
@@ -20,6 +21,7 @@ use crate::config::Config;
 use crate::module::host::Host;
 use crate::module::install::build::ModuleEnvironment;
 use crate::module::Module;
+use crate::module::repository::Repository;
 
 pub const LEVEL_SEPARATOR: char = '.';
 
@@ -148,11 +150,22 @@ pub fn generate_magic() -> Variable {
     Variable::Group(HashMap::from([
         ("pusta".into(), Variable::Group(HashMap::from([
             ("username".into(), Variable::Value(Value::String(whoami::username()))),
-            ("hostname".into(), Variable::Value(Value::String(whoami::hostname())))
+            ("hostname".into(), Variable::Value(Value::String(whoami::hostname()))),
+            ("os".into(), Variable::Value(Value::String(os_id())))
         ])))
     ]))
 }
 
+/// Reads the id of the operating system from the os-release file, e.g. arch or debian
+fn os_id() -> String {
+    ["/etc/os-release", "/usr/lib/os-release"].iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .and_then(|release| release.lines()
+            .find_map(|line| line.strip_prefix("ID="))
+            .map(|id| id.trim().trim_matches(|c| c == '"' || c == '\'').to_owned()))
+        .unwrap_or_else(|| env::consts::OS.to_owned())
+}
+
 /// Constructs injected variables for a list of untouched modules
 pub fn construct_injected(mut list: Vec<&Module>) -> Variable {
 
@@ -187,12 +200,22 @@ pub fn construct_host(list: &Vec<Host>) -> Variable {
     base
 }
 
-pub fn merge_variables(module: &Variable, repository: &Variable, env: &ModuleEnvironment) -> Variable {
+/// Loads the variables of a repository chain, repositories without variables are treated as empty
+pub fn load_repositories(chain: &[&Repository]) -> anyhow::Result<Vec<Variable>> {
+    chain.iter()
+        .map(|r| Ok(r.load_variables()?.unwrap_or_else(Variable::base)))
+        .collect()
+}
+
+/// Merges the variables for a module, the repositories are layered in the order they are provided
+pub fn merge_variables(module: &Module, repositories: &[Variable], env: &ModuleEnvironment) -> Variable {
     let mut base = Variable::base();
 
     // merge variables in order
-    base.merge(module);
-    base.merge(repository);
+    base.merge(&module.variables());
+    for repository in repositories {
+        base.merge(repository);
+    }
     base.merge(&env.injected_variables);
     base.merge(&env.host_variables);
     base.merge(&env.system_variables);